@@ -0,0 +1,53 @@
+//! `--toc`: a contents page listing every heading with its page number, both
+//! linked to the heading.
+
+use crate::layout::{Layout, Run, Style};
+
+/// How far each heading level is indented past the one above it.
+const LEVEL_INDENT: f32 = 15.0;
+
+/// Lays out the contents for `body`, to go in front of it. The page numbers
+/// depend on how long the contents themselves are, so they are laid out
+/// again until that settles.
+pub fn contents(body: &Layout) -> Layout {
+    let mut pages = 1;
+    loop {
+        let contents = contents_with_offset(body, pages);
+        if contents.pages.len() == pages {
+            return contents;
+        }
+        pages = contents.pages.len();
+    }
+}
+
+fn contents_with_offset(body: &Layout, offset: usize) -> Layout {
+    let mut contents = Layout::new(&body.theme, body.fonts.clone());
+    contents.heading(&[Run::plain("Contents")], 1);
+    let top = body.headings.iter().map(|heading| heading.level).min().unwrap_or(1);
+    for heading in &body.headings {
+        let style = Style { link: Some(contents.link(&format!("#{}", heading.slug))), ..Style::default() };
+        let title = Run { text: heading.title.clone(), style };
+        let page = Run { text: (heading.position.page + offset + 1).to_string(), style };
+        contents.contents_entry(&[title], (heading.level - top) as f32 * LEVEL_INDENT, &[page]);
+    }
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_headings_with_the_pages_they_end_up_on() {
+        let mut body = Layout::default();
+        body.heading(&[Run::plain("Intro")], 1);
+        body.new_page();
+        body.heading(&[Run::plain("Details")], 2);
+        let contents = contents(&body);
+        let lines = &contents.pages[0].lines;
+        let text = |index: usize| lines[index].spans.iter().map(|span| span.text.as_str()).collect::<String>();
+        assert_eq!((text(1), text(2), text(3), text(4)), ("Intro".into(), "2".into(), "Details".into(), "3".into()));
+        assert!(lines[3].x > lines[1].x);
+        assert_eq!(contents.links[lines[3].spans[0].style.link.unwrap()], "#details");
+    }
+}
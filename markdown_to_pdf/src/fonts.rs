@@ -0,0 +1,196 @@
+//! The fonts text is set in, and which one each character comes from.
+//!
+//! By default these are the builtin Helvetica and Courier, which PDF viewers
+//! supply but which only cover Western European text. The theme can replace
+//! either family with TrueType or OpenType files, embedded in the PDF, and
+//! name fallback fonts. A character the text's own font lacks is taken from
+//! the first font that has it.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use owned_ttf_parser::{AsFaceRef, OwnedFace};
+
+use crate::layout::Style;
+use crate::metrics::{builtin_covers, char_width, Font};
+use crate::theme::{FontFiles, Theme};
+
+/// A font some text is set in: one of the builtin ones, or an index into
+/// `Fonts::files`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Face {
+    Builtin(Font),
+    File(usize),
+}
+
+pub struct FontFile {
+    pub path: PathBuf,
+    face: OwnedFace,
+}
+
+impl fmt::Debug for FontFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FontFile").field("path", &self.path).finish()
+    }
+}
+
+impl FontFile {
+    fn load(path: &Path) -> Result<FontFile> {
+        let data = fs::read(path).with_context(|| format!("Failed to read font: {}", path.display()))?;
+        let face = OwnedFace::from_vec(data, 0).map_err(|error| anyhow!("Invalid font {}: {}", path.display(), error))?;
+        Ok(FontFile { path: path.to_path_buf(), face })
+    }
+
+    /// The font file as it was read.
+    pub fn data(&self) -> &[u8] {
+        self.face.as_slice()
+    }
+
+    /// The glyph `c` is drawn with, if the font has one.
+    pub fn glyph(&self, c: char) -> Option<u16> {
+        self.face.as_face_ref().glyph_index(c).map(|glyph| glyph.0)
+    }
+
+    /// Advance width of `c`, in thousandths of the font size.
+    fn width(&self, c: char) -> f32 {
+        let face = self.face.as_face_ref();
+        let advance = face.glyph_index(c).and_then(|glyph| face.glyph_hor_advance(glyph));
+        advance.map_or(0.0, |advance| advance as f32 * 1000.0 / face.units_per_em() as f32)
+    }
+}
+
+/// The regular, bold, italic and bold italic faces of a font.
+#[derive(Debug, Clone, Copy)]
+struct Family {
+    regular: Face,
+    bold: Face,
+    italic: Face,
+    bold_italic: Face,
+}
+
+impl Family {
+    fn builtin(mono: bool) -> Family {
+        let face = |bold, italic| Face::Builtin(Font::select(bold, italic, mono));
+        Family { regular: face(false, false), bold: face(true, false), italic: face(false, true), bold_italic: face(true, true) }
+    }
+
+    fn pick(&self, bold: bool, italic: bool) -> Face {
+        match (bold, italic) {
+            (false, false) => self.regular,
+            (true, false) => self.bold,
+            (false, true) => self.italic,
+            (true, true) => self.bold_italic,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Fonts {
+    pub files: Vec<FontFile>,
+    body: Family,
+    mono: Family,
+    fallbacks: Vec<Face>,
+}
+
+impl Default for Fonts {
+    /// Just the builtin fonts.
+    fn default() -> Self {
+        Fonts { files: Vec::new(), body: Family::builtin(false), mono: Family::builtin(true), fallbacks: Vec::new() }
+    }
+}
+
+impl Fonts {
+    /// Loads the font files `theme` names.
+    pub fn load(theme: &Theme) -> Result<Fonts> {
+        let mut fonts = Fonts::default();
+        fonts.body = fonts.family(&theme.font, false)?;
+        fonts.mono = fonts.family(&theme.mono_font, true)?;
+        for path in &theme.fallback_fonts {
+            let face = fonts.add(path)?;
+            fonts.fallbacks.push(face);
+        }
+        Ok(fonts)
+    }
+
+    /// The face for the font at `path`, loading it unless it already has been.
+    fn add(&mut self, path: &Path) -> Result<Face> {
+        if let Some(index) = self.files.iter().position(|file| file.path == path) {
+            return Ok(Face::File(index));
+        }
+        self.files.push(FontFile::load(path)?);
+        Ok(Face::File(self.files.len() - 1))
+    }
+
+    fn family(&mut self, files: &FontFiles, mono: bool) -> Result<Family> {
+        let Some(regular) = &files.regular else {
+            if files.bold.is_some() || files.italic.is_some() || files.bold_italic.is_some() {
+                bail!("A bold or italic font is set without a regular one");
+            }
+            return Ok(Family::builtin(mono));
+        };
+        let regular = self.add(regular)?;
+        let mut face = |path: &Option<PathBuf>, missing: Face| path.as_ref().map_or(Ok(missing), |path| self.add(path));
+        let bold = face(&files.bold, regular)?;
+        let italic = face(&files.italic, regular)?;
+        let bold_italic = face(&files.bold_italic, bold)?;
+        Ok(Family { regular, bold, italic, bold_italic })
+    }
+
+    /// Where `c` comes from in text set in `style`: the style's own face if
+    /// it has the character, otherwise the first of its family's regular
+    /// face, the fallback fonts, the other family and the builtin fonts that
+    /// does. If none has it, the style's own face.
+    pub fn face(&self, style: &Style, c: char) -> Face {
+        let (family, other) = if style.code { (&self.mono, &self.body) } else { (&self.body, &self.mono) };
+        let own = family.pick(style.bold, style.italic);
+        let builtin = Face::Builtin(Font::select(style.bold, style.italic, style.code));
+        [own, family.regular]
+            .into_iter()
+            .chain(self.fallbacks.iter().copied())
+            .chain([other.pick(style.bold, style.italic), other.regular, builtin])
+            .find(|&face| self.covers(face, c))
+            .unwrap_or(own)
+    }
+
+    pub fn covers(&self, face: Face, c: char) -> bool {
+        match face {
+            Face::Builtin(_) => builtin_covers(c),
+            Face::File(index) => self.files[index].glyph(c).is_some_and(|glyph| glyph != 0),
+        }
+    }
+
+    /// Whether any font has `c` for text in `style`.
+    pub fn has(&self, style: &Style, c: char) -> bool {
+        self.covers(self.face(style, c), c)
+    }
+
+    /// Width of `c` in `face`, in thousandths of the font size.
+    pub fn width(&self, face: Face, c: char) -> f32 {
+        match face {
+            Face::Builtin(font) => char_width(font, c) as f32,
+            Face::File(index) => self.files[index].width(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_a_font_that_has_the_character() {
+        let fonts = Fonts::default();
+        let bold = Style { bold: true, ..Style::default() };
+        assert_eq!(fonts.face(&bold, 'é'), Face::Builtin(Font::HelveticaBold));
+        assert_eq!(fonts.face(&Style { code: true, ..bold }, 'x'), Face::Builtin(Font::CourierBold));
+        assert!(fonts.has(&bold, '•'));
+        assert!(!fonts.has(&bold, '☐'));
+        let mut theme = Theme::default();
+        theme.font.bold = Some(PathBuf::from("Bold.ttf"));
+        assert!(Fonts::load(&theme).is_err());
+        theme.font.regular = Some(PathBuf::from("/no/such/font.ttf"));
+        assert!(Fonts::load(&theme).unwrap_err().to_string().contains("/no/such/font.ttf"));
+    }
+}
@@ -0,0 +1,78 @@
+//! Splits the contents of a fenced code block into lines of runs, coloured by
+//! syntect when highlighting is turned on and the fence names a language it
+//! knows.
+
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+
+use crate::layout::{push_text, Run, Style};
+
+/// A light theme, so the colours read on the grey block background.
+const THEME: &str = "InspiredGitHub";
+
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Highlighter {
+    pub fn new() -> Highlighter {
+        let mut themes = ThemeSet::load_defaults();
+        Highlighter {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: themes.themes.remove(THEME).expect("syntect ships the InspiredGitHub theme"),
+        }
+    }
+
+    /// `code` as lines of coloured runs, or `None` if `language` isn't one
+    /// syntect recognises (by name or file extension, so `sh`, `bash`, `rs`
+    /// and `rust` all work).
+    pub fn lines(&self, code: &str, language: &str) -> Option<Vec<Vec<Run>>> {
+        let syntax = self
+            .syntaxes
+            .find_syntax_by_token(language)
+            .or_else(|| self.syntaxes.find_syntax_by_name(language))?;
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        let mut lines = Vec::new();
+        for source_line in code.lines() {
+            let mut runs = Vec::new();
+            for (highlight, text) in highlighter.highlight_line(&format!("{}\n", source_line), &self.syntaxes).ok()? {
+                let style = Style {
+                    code: true,
+                    bold: highlight.font_style.contains(FontStyle::BOLD),
+                    italic: highlight.font_style.contains(FontStyle::ITALIC),
+                    color: Some([highlight.foreground.r, highlight.foreground.g, highlight.foreground.b]),
+                    ..Style::default()
+                };
+                push_text(&mut runs, text.trim_end_matches('\n'), style);
+            }
+            lines.push(runs);
+        }
+        Some(lines)
+    }
+}
+
+/// `code` as lines of uncoloured monospace runs.
+pub fn plain_lines(code: &str) -> Vec<Vec<Run>> {
+    let style = Style { code: true, ..Style::default() };
+    code.lines().map(|line| vec![Run { text: line.to_string(), style }]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colours_known_languages_and_keeps_every_line() {
+        let highlighter = Highlighter::new();
+        let code = "if true; then\n  echo \"hi\"\nfi\n";
+        let lines = highlighter.lines(code, "sh").unwrap();
+        assert_eq!(lines.len(), 3);
+        let text: Vec<String> = lines.iter().map(|runs| runs.iter().map(|run| run.text.as_str()).collect()).collect();
+        assert_eq!(text, vec!["if true; then", "  echo \"hi\"", "fi"]);
+        let colours: Vec<_> = lines.iter().flatten().map(|run| run.style.color).collect();
+        assert!(colours.iter().any(|colour| colour != &colours[0]));
+        assert!(highlighter.lines(code, "no-such-language").is_none());
+    }
+}
@@ -0,0 +1,157 @@
+//! Works out which Markdown files `--input` means: files named outright,
+//! every Markdown file in a directory, files matching a wildcard pattern, or
+//! standard input for `-`.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+/// Extensions a file in a directory needs to be taken for Markdown.
+const EXTENSIONS: [&str; 2] = ["md", "markdown"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Input {
+    pub fn read(&self) -> Result<String> {
+        match self {
+            Input::Stdin => {
+                let mut text = String::new();
+                io::stdin().read_to_string(&mut text).context("Failed to read standard input")?;
+                Ok(text)
+            },
+            Input::File(path) => {
+                fs::read_to_string(path).with_context(|| format!("Failed to read input file: {}", path.display()))
+            },
+        }
+    }
+
+    /// Where the document's images are looked for.
+    pub fn base_dir(&self) -> &Path {
+        match self {
+            Input::File(path) => path.parent().unwrap_or(Path::new(".")),
+            Input::Stdin => Path::new("."),
+        }
+    }
+
+    /// The file name without its extension, for a title or an output name.
+    pub fn stem(&self) -> Option<String> {
+        match self {
+            Input::File(path) => path.file_stem().map(|stem| stem.to_string_lossy().to_string()),
+            Input::Stdin => None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Input::File(path) => path.display().to_string(),
+            Input::Stdin => "standard input".to_string(),
+        }
+    }
+}
+
+/// The inputs `patterns` name, in order. Directories and wildcard patterns
+/// give their files sorted by name.
+pub fn expand(patterns: &[String]) -> Result<Vec<Input>> {
+    let mut inputs = Vec::new();
+    for pattern in patterns {
+        let path = Path::new(pattern);
+        if pattern == "-" {
+            inputs.push(Input::Stdin);
+        } else if path.is_dir() {
+            let files = list(path, |name| {
+                Path::new(name).extension().is_some_and(|extension| EXTENSIONS.iter().any(|known| extension == *known))
+            })?;
+            if files.is_empty() {
+                bail!("No Markdown files in {}", path.display());
+            }
+            inputs.extend(files.into_iter().map(Input::File));
+        } else if pattern.contains(['*', '?']) {
+            let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+            let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            if dir.to_string_lossy().contains(['*', '?']) {
+                bail!("{}: wildcards only work in the file name, not the directory", pattern);
+            }
+            let files = list(dir, |file| matches(&name, file))?;
+            if files.is_empty() {
+                bail!("No files match {}", pattern);
+            }
+            inputs.extend(files.into_iter().map(Input::File));
+        } else {
+            inputs.push(Input::File(path.to_path_buf()));
+        }
+    }
+    Ok(inputs)
+}
+
+/// The files in `dir` whose names pass `keep`, sorted.
+fn list(dir: &Path, keep: impl Fn(&str) -> bool) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir).with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+        if entry.path().is_file() && keep(&entry.file_name().to_string_lossy()) {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of
+/// characters and `?` for any one character.
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // matched[j]: whether the pattern so far matches the first j characters.
+    let mut matched = vec![false; name.len() + 1];
+    matched[0] = true;
+    for &p in &pattern {
+        let mut next = vec![false; name.len() + 1];
+        for j in 0..=name.len() {
+            next[j] = match p {
+                '*' => matched[j] || (j > 0 && next[j - 1]),
+                '?' => j > 0 && matched[j - 1],
+                _ => j > 0 && matched[j - 1] && name[j - 1] == p,
+            };
+        }
+        matched = next;
+    }
+    matched[name.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("*.md", "notes.md"));
+        assert!(matches("week-??.md", "week-07.md"));
+        assert!(matches("*", ""));
+        assert!(!matches("*.md", "notes.markdown"));
+        assert!(!matches("week-??.md", "week-7.md"));
+    }
+
+    #[test]
+    fn expands_directories_and_patterns_in_name_order() {
+        let dir = std::env::temp_dir().join(format!("markdown_to_pdf-inputs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["b.md", "a.markdown", "c.txt"] {
+            fs::write(dir.join(name), "# x").unwrap();
+        }
+        let files = |inputs: Vec<Input>| -> Vec<String> {
+            inputs.iter().map(|input| input.stem().unwrap_or_else(|| "-".to_string())).collect()
+        };
+        let dir_name = dir.to_string_lossy().to_string();
+        assert_eq!(files(expand(std::slice::from_ref(&dir_name)).unwrap()), vec!["a", "b"]);
+        assert_eq!(files(expand(&[format!("{}/*.md", dir_name), "-".to_string()]).unwrap()), vec!["b", "-"]);
+        assert!(expand(&[format!("{}/*.pdf", dir_name)]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
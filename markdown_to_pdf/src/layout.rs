@@ -0,0 +1,1129 @@
+//! Places text on pages: breaks it into lines that fit between the margins
+//! and starts a new page whenever the current one is full.
+//!
+//! Everything here is in points measured from the top-left corner of the
+//! page, which is how a document reads; `pdf` turns that into PDF's
+//! bottom-left coordinates when the pages are drawn.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::fonts::{Face, Fonts};
+use crate::theme::Theme;
+
+/// How far the baseline sits below the top of the glyphs, as a multiple of the font size.
+pub const ASCENT: f32 = 0.8;
+/// Space between a code block's shading and its text.
+const CODE_PADDING: f32 = 6.0;
+/// Tab stops in code blocks, in characters.
+const TAB_WIDTH: usize = 4;
+/// Space between a table cell's edges and its text.
+const CELL_PADDING: f32 = 4.0;
+/// How far each level of list is indented.
+const LIST_INDENT: f32 = 24.0;
+/// Space between a list item's marker and its text.
+const MARKER_GAP: f32 = 6.0;
+/// How far a blockquote is indented, and how thick the bar beside it is.
+const QUOTE_INDENT: f32 = 15.0;
+const QUOTE_BAR: f32 = 3.0;
+/// Images are assumed to be at screen resolution when deciding how big to
+/// draw them, so a screenshot comes out the size it was on screen.
+const IMAGE_DPI: f32 = 96.0;
+
+/// How a stretch of inline text is set.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    /// Inline code, set in the monospace font.
+    pub code: bool,
+    pub strike: bool,
+    /// Text colour; the theme's text colour if `None`.
+    pub color: Option<[u8; 3]>,
+    /// Where the text links to, as an index into `Layout::links`.
+    pub link: Option<usize>,
+}
+
+/// Inline text in one style, as it comes out of the Markdown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub text: String,
+    pub style: Style,
+}
+
+impl Run {
+    pub fn plain(text: &str) -> Run {
+        Run { text: text.to_string(), style: Style::default() }
+    }
+}
+
+/// Appends `text` to `runs`, extending the last run if it has the same style.
+pub fn push_text(runs: &mut Vec<Run>, text: &str, style: Style) {
+    match runs.last_mut() {
+        Some(last) if last.style == style => last.text.push_str(text),
+        _ => runs.push(Run { text: text.to_string(), style }),
+    }
+}
+
+/// Part of a line set in one style and face, `x` points in from the start
+/// of the line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub x: f32,
+    pub width: f32,
+    pub text: String,
+    pub style: Style,
+    pub face: Face,
+}
+
+/// One line of text, already positioned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub x: f32,
+    /// Baseline, from the top of the page.
+    pub y: f32,
+    pub size: f32,
+    pub spans: Vec<Span>,
+    /// The block the line belongs to, as an index into `Layout::blocks`;
+    /// `None` for running headers and footers.
+    pub block: Option<usize>,
+}
+
+/// A shaded area behind text, such as a code block's background.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    /// Top edge, from the top of the page.
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// A straight line drawn on the page, such as a table border.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub from: (f32, f32),
+    pub to: (f32, f32),
+    pub thickness: f32,
+    /// The theme's text colour if `None`.
+    pub color: Option<[u8; 3]>,
+}
+
+/// Where an image is drawn. `image` says which one, in whatever list of
+/// images the layout was given them from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Picture {
+    pub image: usize,
+    pub x: f32,
+    /// Top edge, from the top of the page.
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Page {
+    /// Drawn before, and so underneath, the lines.
+    pub shading: Vec<Rect>,
+    pub rules: Vec<Rule>,
+    pub pictures: Vec<Picture>,
+    pub lines: Vec<Line>,
+}
+
+/// What a block of the document is. A block's lines point back to it; a
+/// rule has none.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Block {
+    Paragraph,
+    /// A paragraph that starts a list item, with the item's marker.
+    ListItem,
+    Heading(usize),
+    CodeBlock,
+    Table,
+    /// An image and its caption.
+    Image,
+    Rule,
+    ContentsEntry,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// A table as it comes out of the Markdown: one list of runs per cell.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Table {
+    pub alignments: Vec<Align>,
+    pub header: Vec<Vec<Run>>,
+    pub rows: Vec<Vec<Vec<Run>>>,
+}
+
+/// A table row wrapped to its column widths: the lines of each cell, and the
+/// height of the row.
+struct SetRow {
+    cells: Vec<Vec<Vec<Span>>>,
+    height: f32,
+}
+
+/// A point in the document: a page index, and a distance from the top of
+/// that page.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub page: usize,
+    pub y: f32,
+}
+
+/// A heading that has been set, for the table of contents and the outline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadingMark {
+    pub level: usize,
+    pub title: String,
+    /// The anchor links to the heading use, without the `#`.
+    pub slug: String,
+    pub position: Position,
+}
+
+#[derive(Debug)]
+pub struct Layout {
+    pub theme: Theme,
+    pub fonts: Rc<Fonts>,
+    pub pages: Vec<Page>,
+    /// Link targets, as written in the Markdown: URLs, or `#` and an anchor.
+    pub links: Vec<String>,
+    pub headings: Vec<HeadingMark>,
+    /// Places internal links can go to, by name.
+    pub anchors: HashMap<String, Position>,
+    /// The blocks set so far, in order.
+    pub blocks: Vec<Block>,
+    /// Top of the next line, from the top of the page.
+    cursor: f32,
+    /// How far lists and blockquotes have moved text in from the margin.
+    indent: f32,
+    /// A list item's marker, waiting for the item's first line.
+    marker: Option<String>,
+    /// Where each blockquote being set started.
+    quotes: Vec<Position>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::new(&Theme::default(), Rc::default())
+    }
+}
+
+impl Layout {
+    pub fn new(theme: &Theme, fonts: Rc<Fonts>) -> Layout {
+        Layout {
+            theme: theme.clone(),
+            fonts,
+            pages: vec![Page::default()],
+            links: Vec::new(),
+            headings: Vec::new(),
+            anchors: HashMap::new(),
+            blocks: Vec::new(),
+            cursor: theme.margins.top,
+            indent: 0.0,
+            marker: None,
+            quotes: Vec::new(),
+        }
+    }
+
+    /// The index of `target` in `links`, adding it if it is new.
+    pub fn link(&mut self, target: &str) -> usize {
+        match self.links.iter().position(|link| link == target) {
+            Some(index) => index,
+            None => {
+                self.links.push(target.to_string());
+                self.links.len() - 1
+            },
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position { page: self.pages.len() - 1, y: self.cursor }
+    }
+
+    /// Names the current position, so `#name` links come here.
+    pub fn anchor(&mut self, name: &str) {
+        let position = self.position();
+        self.anchors.insert(name.to_string(), position);
+    }
+
+    /// Puts the pages of `front` before these ones, with everything that
+    /// refers to a page moved along to match.
+    pub fn prepend(&mut self, front: Layout) {
+        let shift = front.pages.len();
+        for heading in &mut self.headings {
+            heading.position.page += shift;
+        }
+        for position in self.anchors.values_mut() {
+            position.page += shift;
+        }
+        for line in self.pages.iter_mut().flat_map(|page| &mut page.lines) {
+            line.block = line.block.map(|block| block + front.blocks.len());
+        }
+        let mut pages = front.pages;
+        for line in pages.iter_mut().flat_map(|page| &mut page.lines) {
+            for span in &mut line.spans {
+                span.style.link = span.style.link.map(|link| self.link(&front.links[link]));
+            }
+        }
+        pages.append(&mut self.pages);
+        self.pages = pages;
+        self.blocks.splice(0..0, front.blocks);
+        self.headings.splice(0..0, front.headings);
+        for (name, position) in front.anchors {
+            self.anchors.entry(name).or_insert(position);
+        }
+    }
+
+    /// The lines of block number `block`, each with the index of its page.
+    pub fn block_lines(&self, block: usize) -> impl Iterator<Item = (usize, &Line)> {
+        self.pages
+            .iter()
+            .enumerate()
+            .flat_map(|(index, page)| page.lines.iter().map(move |line| (index, line)))
+            .filter(move |(_, line)| line.block == Some(block))
+    }
+
+    /// Starts a block; the lines placed from here on belong to it.
+    fn start_block(&mut self, block: Block) {
+        self.blocks.push(block);
+    }
+
+    /// The index of the block being set.
+    fn block(&self) -> Option<usize> {
+        self.blocks.len().checked_sub(1)
+    }
+
+    fn left(&self) -> f32 {
+        self.theme.margins.left + self.indent
+    }
+
+    /// Width between the left margin, indented for lists and blockquotes,
+    /// and the right margin.
+    fn text_width(&self) -> f32 {
+        self.theme.text_width() - self.indent
+    }
+
+    fn bottom(&self) -> f32 {
+        self.theme.page_size.height - self.theme.margins.bottom
+    }
+
+    fn at_page_top(&self) -> bool {
+        self.cursor <= self.theme.margins.top
+    }
+
+    fn line_height(&self, size: f32) -> f32 {
+        size * self.theme.line_spacing
+    }
+
+    /// The space left after a paragraph.
+    fn paragraph_gap(&self) -> f32 {
+        self.line_height(self.theme.font_size) * self.theme.paragraph_spacing
+    }
+
+    pub fn new_page(&mut self) {
+        self.pages.push(Page::default());
+        self.cursor = self.theme.margins.top;
+    }
+
+    /// Starts a new page, unless nothing has been set on this one yet.
+    pub fn page_break(&mut self) {
+        if !self.at_page_top() {
+            self.new_page();
+        }
+    }
+
+    /// Starts a new page unless `height` more points fit on this one. A page
+    /// that is still empty takes whatever it is given.
+    fn ensure_room(&mut self, height: f32) {
+        if self.cursor + height > self.bottom() && !self.at_page_top() {
+            self.new_page();
+        }
+    }
+
+    fn place_line(&mut self, x: f32, spans: Vec<Span>, size: f32) {
+        self.ensure_room(self.line_height(size));
+        self.push_line(x, spans, size);
+    }
+
+    /// Adds a line at the cursor, whether or not it fits, with the pending
+    /// list marker, if there is one, hung in the indent beside it.
+    fn push_line(&mut self, x: f32, spans: Vec<Span>, size: f32) {
+        let height = self.line_height(size);
+        let y = self.cursor + (height - size) / 2.0 + size * ASCENT;
+        if let Some(marker) = self.marker.take() {
+            let marker = wrap(&self.fonts, &[Run::plain(&marker)], size, f32::INFINITY).pop().unwrap_or_default();
+            let width = marker.last().map_or(0.0, |span| span.x + span.width);
+            let line = Line { x: self.left() - MARKER_GAP - width, y, size, spans: marker, block: self.block() };
+            self.pages.last_mut().unwrap().lines.push(line);
+        }
+        let block = self.block();
+        self.pages.last_mut().unwrap().lines.push(Line { x, y, size, spans, block });
+        self.cursor += height;
+    }
+
+    /// Sets `runs` as a paragraph, wrapped to the text width and followed by
+    /// paragraph spacing.
+    pub fn paragraph(&mut self, runs: &[Run]) {
+        self.start_block(if self.marker.is_some() { Block::ListItem } else { Block::Paragraph });
+        let x = self.left();
+        let size = self.theme.font_size;
+        for line in wrap(&self.fonts, runs, size, self.text_width()) {
+            self.place_line(x, line, size);
+        }
+        self.cursor += self.paragraph_gap();
+    }
+
+    /// Indents what follows for a list, until `end_list`.
+    pub fn start_list(&mut self) {
+        self.indent += LIST_INDENT;
+    }
+
+    pub fn end_list(&mut self) {
+        self.indent -= LIST_INDENT;
+    }
+
+    /// Sets `marker` (a bullet, number or checkbox) in the indent beside the
+    /// next line, which starts a list item. A later marker replaces it.
+    pub fn list_marker(&mut self, marker: &str) {
+        self.marker = Some(marker.to_string());
+    }
+
+    /// Indents what follows for a blockquote, until `end_quote` draws a bar
+    /// beside it.
+    pub fn start_quote(&mut self) {
+        self.quotes.push(self.position());
+        self.indent += QUOTE_INDENT;
+    }
+
+    /// Draws the bar beside the blockquote that is ending, on every page it
+    /// reached.
+    pub fn end_quote(&mut self) {
+        let Some(start) = self.quotes.pop() else { return };
+        self.indent -= QUOTE_INDENT;
+        let x = self.left() + QUOTE_BAR / 2.0;
+        let last = self.pages.len() - 1;
+        let end = self.cursor - self.paragraph_gap();
+        let spacing = self.theme.line_spacing;
+        for index in start.page..=last {
+            let page = &mut self.pages[index];
+            let top = if index == start.page { start.y } else { self.theme.margins.top };
+            let bottom = if index == last {
+                end
+            } else {
+                // The bottom of whatever is lowest on the page.
+                let lines = page.lines.iter().map(|line| line.y + line.size * (spacing / 2.0 + 0.5 - ASCENT));
+                let pictures = page.pictures.iter().map(|picture| picture.y + picture.height);
+                let shading = page.shading.iter().map(|rect| rect.y + rect.height);
+                lines.chain(pictures).chain(shading).fold(top, f32::max)
+            };
+            if bottom > top {
+                let color = Some(self.theme.quote_color.0);
+                page.rules.push(Rule { from: (x, top), to: (x, bottom), thickness: QUOTE_BAR, color });
+            }
+        }
+    }
+
+    /// Sets a heading in bold at its level's size, kept on the same page as
+    /// at least the first line of what follows it.
+    ///
+    /// Headings are recorded in `headings`, and anchored under a slug made
+    /// the way GitHub makes them, so `[see](#getting-started)` links work.
+    pub fn heading(&mut self, runs: &[Run], level: usize) {
+        let size = self.theme.heading_sizes[level.clamp(1, 6) - 1];
+        let color = self.theme.heading_color.0;
+        let runs: Vec<Run> = bold(runs)
+            .into_iter()
+            .map(|run| Run { style: Style { color: run.style.color.or(Some(color)), ..run.style }, ..run })
+            .collect();
+        self.start_block(Block::Heading(level));
+        let lines = wrap(&self.fonts, &runs, size, self.text_width());
+        if !self.at_page_top() {
+            self.cursor += size * 0.5;
+        }
+        self.ensure_room(lines.len() as f32 * self.line_height(size) + self.line_height(self.theme.font_size));
+
+        let title: String = runs.iter().map(|run| run.text.as_str()).collect();
+        let mut slug = slug(&title);
+        if self.anchors.contains_key(&slug) {
+            let base = slug.clone();
+            slug = (1..).map(|n| format!("{}-{}", base, n)).find(|slug| !self.anchors.contains_key(slug)).unwrap();
+        }
+        self.anchor(&slug);
+        let position = self.position();
+        self.headings.push(HeadingMark { level, title: title.trim().to_string(), slug, position });
+
+        let x = self.left();
+        for line in lines {
+            self.place_line(x, line, size);
+        }
+        self.cursor += size * 0.25;
+    }
+
+    /// Sets a line of a table of contents: `runs` indented by `indent`,
+    /// with `page` right-aligned at the margin.
+    pub fn contents_entry(&mut self, runs: &[Run], indent: f32, page: &[Run]) {
+        let size = self.theme.font_size;
+        let x = self.left() + indent;
+        let number = wrap(&self.fonts, page, size, f32::INFINITY).pop().unwrap_or_default();
+        let number_width = number.last().map_or(0.0, |span| span.x + span.width);
+        let lines = wrap(&self.fonts, runs, size, self.text_width() - indent - number_width - size);
+        if lines.is_empty() {
+            return;
+        }
+        self.start_block(Block::ContentsEntry);
+        for line in lines {
+            self.place_line(x, line, size);
+        }
+        let y = self.pages.last().unwrap().lines.last().unwrap().y;
+        let x = self.left() + self.text_width() - number_width;
+        let block = self.block();
+        self.pages.last_mut().unwrap().lines.push(Line { x, y, size, spans: number, block });
+    }
+
+    /// A rule across `fraction` of the text width from the left margin, with
+    /// space around it.
+    pub fn horizontal_rule(&mut self, fraction: f32) {
+        self.start_block(Block::Rule);
+        let (x, width) = (self.left(), self.text_width() * fraction);
+        let height = self.line_height(self.theme.font_size);
+        self.ensure_room(height);
+        self.cursor += height / 2.0;
+        self.rule((x, self.cursor), (x + width, self.cursor), 0.5);
+        self.cursor += height / 2.0;
+    }
+
+    /// Sets a code block line for line, whitespace and all, on a shaded
+    /// background. Lines too long for the page are broken at the margin, and
+    /// a block that doesn't fit on the page is continued on the next one,
+    /// shading and all.
+    pub fn code_block(&mut self, lines: &[Vec<Run>]) {
+        self.start_block(Block::CodeBlock);
+        let size = self.theme.code_size;
+        let line_height = self.line_height(size);
+        let x = self.left() + CODE_PADDING;
+        let width = self.text_width() - 2.0 * CODE_PADDING;
+        let rows: Vec<Vec<Span>> = lines.iter().flat_map(|line| wrap_code(&self.fonts, line, size, width)).collect();
+        self.ensure_room(line_height + 2.0 * CODE_PADDING);
+        let mut top = self.cursor;
+        self.cursor += CODE_PADDING;
+        for row in rows {
+            if self.cursor + line_height + CODE_PADDING > self.bottom() {
+                self.cursor += CODE_PADDING;
+                self.shade(top);
+                self.new_page();
+                top = self.cursor;
+                self.cursor += CODE_PADDING;
+            }
+            self.push_line(x, row, size);
+        }
+        self.cursor += CODE_PADDING;
+        self.shade(top);
+        self.cursor += self.paragraph_gap();
+    }
+
+    /// Sets a table across the text width. Columns get their natural width
+    /// when everything fits, and otherwise share the width out in proportion
+    /// to how much they need beyond their longest word, with the cells
+    /// wrapped to fit. A row never splits across pages, and the header row
+    /// is repeated at the top of every page the table continues on.
+    pub fn table(&mut self, table: &Table) {
+        let columns = table.alignments.len().max(table.header.len());
+        if columns == 0 {
+            return;
+        }
+        self.start_block(Block::Table);
+        let header: Vec<Vec<Run>> = table.header.iter().map(|cell| bold(cell)).collect();
+        let size = self.theme.font_size;
+        let rows = std::iter::once(&header).chain(&table.rows);
+        let widths = column_widths(&self.fonts, rows, columns, self.text_width(), size);
+        let header = self.set_row(&header, &widths);
+        let rows: Vec<SetRow> = table.rows.iter().map(|row| self.set_row(row, &widths)).collect();
+
+        self.ensure_room(header.height + rows.first().map_or(0.0, |row| row.height));
+        self.place_row(&header, &widths, &table.alignments, 1.0);
+        for row in &rows {
+            if self.cursor + row.height > self.bottom() && !self.at_page_top() {
+                self.new_page();
+                self.place_row(&header, &widths, &table.alignments, 1.0);
+            }
+            self.place_row(row, &widths, &table.alignments, 0.5);
+        }
+        self.cursor += self.paragraph_gap();
+    }
+
+    /// Places a row at the cursor with a rule under it `rule` points thick,
+    /// and one above it too if it starts the table on this page.
+    fn place_row(&mut self, row: &SetRow, widths: &[f32], alignments: &[Align], rule: f32) {
+        let top = self.cursor;
+        let left = self.left();
+        let right = left + widths.iter().sum::<f32>();
+        if self.pages.last().unwrap().rules.last().is_none_or(|last| last.from != (left, top)) {
+            self.rule((left, top), (right, top), 1.0);
+        }
+        let mut x = left;
+        for (column, lines) in row.cells.iter().enumerate() {
+            let inner = widths[column] - 2.0 * CELL_PADDING;
+            self.cursor = top + CELL_PADDING;
+            for line in lines {
+                let width = line.last().map_or(0.0, |span| span.x + span.width);
+                let offset = match alignments.get(column).copied().unwrap_or_default() {
+                    Align::Left => 0.0,
+                    Align::Center => (inner - width) / 2.0,
+                    Align::Right => inner - width,
+                };
+                self.push_line(x + CELL_PADDING + offset, line.clone(), self.theme.font_size);
+            }
+            x += widths[column];
+        }
+        self.cursor = top + row.height;
+        self.rule((left, self.cursor), (right, self.cursor), rule);
+    }
+
+    /// Wraps each cell of a row to its column, leaving out cells beyond the
+    /// last column and making up missing ones.
+    fn set_row(&self, row: &[Vec<Run>], widths: &[f32]) -> SetRow {
+        let size = self.theme.font_size;
+        let cells: Vec<Vec<Vec<Span>>> = widths
+            .iter()
+            .enumerate()
+            .map(|(column, width)| {
+                let cell = row.get(column).map_or(&[][..], |cell| cell.as_slice());
+                wrap(&self.fonts, cell, size, width - 2.0 * CELL_PADDING)
+            })
+            .collect();
+        let lines = cells.iter().map(Vec::len).max().unwrap_or(0).max(1);
+        SetRow { cells, height: lines as f32 * self.line_height(size) + 2.0 * CELL_PADDING }
+    }
+
+    fn rule(&mut self, from: (f32, f32), to: (f32, f32), thickness: f32) {
+        self.pages.last_mut().unwrap().rules.push(Rule { from, to, thickness, color: None });
+    }
+
+    /// Places image number `image`, `pixels` wide and high, centred on a line
+    /// of its own with `caption` set in italics under it. It is scaled down,
+    /// never up, to fit the text width and the page, and kept on the same
+    /// page as its caption.
+    pub fn image(&mut self, image: usize, pixels: (u32, u32), caption: &[Run]) {
+        self.start_block(Block::Image);
+        let (left, text_width) = (self.left(), self.text_width());
+        let size = self.theme.small_size;
+        let caption: Vec<Run> =
+            caption.iter().map(|run| Run { text: run.text.clone(), style: Style { italic: true, ..run.style } }).collect();
+        let caption = wrap(&self.fonts, &caption, size, text_width);
+        let caption_height = caption.len() as f32 * self.line_height(size);
+
+        let (mut width, mut height) = (pixels.0 as f32 * 72.0 / IMAGE_DPI, pixels.1 as f32 * 72.0 / IMAGE_DPI);
+        let max_height = self.bottom() - self.theme.margins.top - caption_height;
+        let scale = (text_width / width).min(max_height / height).min(1.0);
+        width *= scale;
+        height *= scale;
+
+        self.ensure_room(height + caption_height);
+        let picture = Picture { image, x: left + (text_width - width) / 2.0, y: self.cursor, width, height };
+        self.pages.last_mut().unwrap().pictures.push(picture);
+        self.cursor += height;
+        for line in caption {
+            let line_width = line.last().map_or(0.0, |span| span.x + span.width);
+            self.push_line(left + (text_width - line_width) / 2.0, line, size);
+        }
+        self.cursor += self.paragraph_gap();
+    }
+
+    /// Shades the text width from `top` down to the cursor.
+    fn shade(&mut self, top: f32) {
+        let rect = Rect { x: self.left(), y: top, width: self.text_width(), height: self.cursor - top };
+        self.pages.last_mut().unwrap().shading.push(rect);
+    }
+
+    /// Adds the theme's header and footer to every page, filling in
+    /// `{title}`, `{date}`, `{page}` and `{pages}`. Each sits halfway into
+    /// its margin, so this is done last, once the page count is known.
+    pub fn running_heads(&mut self, title: &str, date: &str) {
+        let theme = self.theme.clone();
+        let size = theme.small_size;
+        let style = Style { color: Some(theme.running_color.0), ..Style::default() };
+        let header_y = theme.margins.top / 2.0 + size * ASCENT / 2.0;
+        let footer_y = theme.page_size.height - theme.margins.bottom / 2.0 + size * ASCENT / 2.0;
+        let count = self.pages.len();
+        for (index, page) in self.pages.iter_mut().enumerate() {
+            for (slots, y) in [(&theme.header, header_y), (&theme.footer, footer_y)] {
+                for (template, align) in [(&slots.left, Align::Left), (&slots.center, Align::Center), (&slots.right, Align::Right)] {
+                    let text = template
+                        .replace("{title}", title)
+                        .replace("{date}", date)
+                        .replace("{pages}", &count.to_string())
+                        .replace("{page}", &(index + 1).to_string());
+                    let Some(spans) = wrap(&self.fonts, &[Run { text, style }], size, f32::INFINITY).pop() else { continue };
+                    let width = spans.last().map_or(0.0, |span| span.x + span.width);
+                    let x = theme.margins.left
+                        + match align {
+                            Align::Left => 0.0,
+                            Align::Center => (theme.text_width() - width) / 2.0,
+                            Align::Right => theme.text_width() - width,
+                        };
+                    page.lines.push(Line { x, y, size, spans, block: None });
+                }
+            }
+        }
+    }
+}
+
+/// Lowercased, with spaces turned into hyphens and punctuation dropped.
+pub fn slug(title: &str) -> String {
+    title
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+fn bold(runs: &[Run]) -> Vec<Run> {
+    runs.iter().map(|run| Run { text: run.text.clone(), style: Style { bold: true, ..run.style } }).collect()
+}
+
+/// Widths of the columns of a table `max_width` wide with text `size`
+/// points high, padding included.
+fn column_widths<'a>(
+    fonts: &Fonts,
+    rows: impl Iterator<Item = &'a Vec<Vec<Run>>>,
+    columns: usize,
+    max_width: f32,
+    size: f32,
+) -> Vec<f32> {
+    let mut natural = vec![2.0 * CELL_PADDING; columns];
+    let mut minimum = natural.clone();
+    for row in rows {
+        for (column, cell) in row.iter().enumerate().take(columns) {
+            let line_width = wrap(fonts, cell, size, f32::INFINITY)
+                .first()
+                .and_then(|line| line.last())
+                .map_or(0.0, |span| span.x + span.width);
+            // Rounded up so the text is sure to fit on one line when it is wrapped.
+            natural[column] = natural[column].max((line_width + 2.0 * CELL_PADDING).ceil());
+            minimum[column] = minimum[column].max((longest_word(fonts, cell, size) + 2.0 * CELL_PADDING).ceil());
+        }
+    }
+    let total: f32 = natural.iter().sum();
+    if total <= max_width {
+        return natural;
+    }
+    let least: f32 = minimum.iter().sum();
+    if least >= max_width {
+        return minimum.iter().map(|width| width * max_width / least).collect();
+    }
+    let spare = (max_width - least) / (total - least);
+    minimum.iter().zip(&natural).map(|(min, nat)| min + (nat - min) * spare).collect()
+}
+
+fn longest_word(fonts: &Fonts, runs: &[Run], size: f32) -> f32 {
+    let mut longest: f32 = 0.0;
+    let mut word = 0.0;
+    for run in runs {
+        for c in run.text.chars() {
+            if c.is_whitespace() {
+                word = 0.0;
+            } else {
+                word += fonts.width(fonts.face(&run.style, c), c) * size / 1000.0;
+                longest = longest.max(word);
+            }
+        }
+    }
+    longest
+}
+
+type Styled = (char, Style);
+
+fn width(fonts: &Fonts, chars: &[Styled], size: f32) -> f32 {
+    chars.iter().map(|(c, style)| fonts.width(fonts.face(style, *c), *c)).sum::<f32>() * size / 1000.0
+}
+
+/// Joins a line's characters back up into spans of one style and face each.
+fn spans(fonts: &Fonts, chars: &[Styled], size: f32) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut x = 0.0;
+    for &(c, style) in chars {
+        let face = fonts.face(&style, c);
+        let advance = fonts.width(face, c) * size / 1000.0;
+        match spans.last_mut() {
+            Some(span) if span.style == style && span.face == face => {
+                span.text.push(c);
+                span.width += advance;
+            },
+            _ => spans.push(Span { x, width: advance, text: c.to_string(), style, face }),
+        }
+        x += advance;
+    }
+    spans
+}
+
+/// Breaks a word too wide for a line into pieces that fit.
+fn split_word(fonts: &Fonts, word: Vec<Styled>, size: f32, max_width: f32) -> Vec<Vec<Styled>> {
+    let mut pieces = Vec::new();
+    let mut piece = Vec::new();
+    for styled in word {
+        piece.push(styled);
+        if width(fonts, &piece, size) > max_width && piece.len() > 1 {
+            piece.pop();
+            pieces.push(std::mem::take(&mut piece));
+            piece.push(styled);
+        }
+    }
+    pieces.push(piece);
+    pieces
+}
+
+/// Breaks a line of code into rows no wider than `max_width`, keeping every
+/// space and expanding tabs. An empty line still gives one (empty) row.
+fn wrap_code(fonts: &Fonts, runs: &[Run], size: f32, max_width: f32) -> Vec<Vec<Span>> {
+    let mut rows = Vec::new();
+    let mut row: Vec<Styled> = Vec::new();
+    let mut column = 0;
+    for run in runs {
+        for c in run.text.chars() {
+            let expanded = if c == '\t' { TAB_WIDTH - column % TAB_WIDTH } else { 1 };
+            for _ in 0..expanded {
+                row.push((if c == '\t' { ' ' } else { c }, run.style));
+                column += 1;
+                if width(fonts, &row, size) > max_width && row.len() > 1 {
+                    let last = row.pop().unwrap();
+                    rows.push(std::mem::replace(&mut row, vec![last]));
+                }
+            }
+        }
+    }
+    rows.push(row);
+    rows.iter().map(|row| spans(fonts, row, size)).collect()
+}
+
+/// Greedy word wrap: as many words on each line as fit in `max_width`. A word
+/// may change style part way through, and the space before a word keeps the
+/// style it had in the source, so struck-through phrases stay struck through.
+pub fn wrap(fonts: &Fonts, runs: &[Run], size: f32, max_width: f32) -> Vec<Vec<Span>> {
+    let mut words: Vec<(Option<Style>, Vec<Styled>)> = Vec::new();
+    let mut space: Option<Style> = None;
+    let mut word: Vec<Styled> = Vec::new();
+    for run in runs {
+        for c in run.text.chars() {
+            if c.is_whitespace() {
+                if !word.is_empty() {
+                    words.push((space.take(), std::mem::take(&mut word)));
+                }
+                space.get_or_insert(run.style);
+            } else {
+                word.push((c, run.style));
+            }
+        }
+    }
+    if !word.is_empty() {
+        words.push((space, word));
+    }
+
+    let mut lines = Vec::new();
+    let mut line: Vec<Styled> = Vec::new();
+    for (space, word) in words {
+        if !line.is_empty() {
+            let mut candidate = line.clone();
+            candidate.push((' ', space.unwrap_or_default()));
+            candidate.extend(&word);
+            if width(fonts, &candidate, size) <= max_width {
+                line = candidate;
+                continue;
+            }
+            lines.push(std::mem::take(&mut line));
+        }
+        let mut pieces = split_word(fonts, word, size, max_width);
+        line = pieces.pop().unwrap_or_default();
+        lines.extend(pieces);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines.iter().map(|line| spans(fonts, line, size)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::{PageSize, Slots};
+
+    fn texts(lines: &[Vec<Span>]) -> Vec<String> {
+        lines.iter().map(|line| line.iter().map(|span| span.text.as_str()).collect()).collect()
+    }
+
+    #[test]
+    fn wraps_at_word_boundaries_within_the_width() {
+        let lines = wrap(&Fonts::default(), &[Run::plain("the quick brown fox jumps over the lazy dog")], 12.0, 100.0);
+        assert_eq!(texts(&lines), vec!["the quick brown", "fox jumps over the", "lazy dog"]);
+        assert!(lines.iter().all(|line| line.iter().map(|span| span.width).sum::<f32>() <= 100.0));
+    }
+
+    #[test]
+    fn splits_words_longer_than_a_line() {
+        let lines = wrap(&Fonts::default(), &[Run::plain(&"m".repeat(30))], 12.0, 100.0);
+        assert_eq!(texts(&lines).concat(), "m".repeat(30));
+        assert!(lines.len() > 1);
+    }
+
+    #[test]
+    fn keeps_styles_across_runs_and_line_breaks() {
+        let bold = Style { bold: true, ..Style::default() };
+        let runs = [Run::plain("plain "), Run { text: "bold words here".to_string(), style: bold }, Run::plain("!")];
+        let lines = wrap(&Fonts::default(), &runs, 12.0, 80.0);
+        assert_eq!(texts(&lines), vec!["plain bold", "words here!"]);
+        assert_eq!(lines[0][0].text, "plain ");
+        assert_eq!(lines[0][1].text, "bold");
+        assert_eq!(lines[0][1].x, lines[0][0].width);
+        assert_eq!(lines[1][0].style, bold);
+        assert_eq!(lines[1][1].text, "!");
+        assert_eq!(lines[1][1].style, Style::default());
+    }
+
+    #[test]
+    fn keeps_whitespace_in_code_and_expands_tabs() {
+        let code = Style { code: true, ..Style::default() };
+        let rows = wrap_code(&Fonts::default(), &[Run { text: "\tif  x:".to_string(), style: code }], 10.0, 200.0);
+        assert_eq!(texts(&rows), vec!["    if  x:"]);
+        let rows = wrap_code(&Fonts::default(), &[Run { text: "x".repeat(50), style: code }], 10.0, 120.0);
+        assert_eq!(texts(&rows), vec!["x".repeat(20), "x".repeat(20), "x".repeat(10)]);
+        assert_eq!(wrap_code(&Fonts::default(), &[], 10.0, 120.0).len(), 1);
+    }
+
+    #[test]
+    fn shades_code_blocks_on_every_page_they_reach() {
+        let mut layout = Layout::default();
+        let code = Style { code: true, ..Style::default() };
+        let lines: Vec<Vec<Run>> = (0..80).map(|n| vec![Run { text: format!("line {}", n), style: code }]).collect();
+        layout.code_block(&lines);
+        assert_eq!(layout.pages.len(), 2);
+        for page in &layout.pages {
+            let shade = &page.shading[0];
+            assert!(page.lines.iter().all(|line| line.y > shade.y && line.y < shade.y + shade.height));
+            assert!(shade.y + shade.height <= layout.bottom());
+        }
+        let lines: usize = layout.pages.iter().map(|page| page.lines.len()).sum();
+        assert_eq!(lines, 80);
+    }
+
+    fn cells(texts: &[&str]) -> Vec<Vec<Run>> {
+        texts.iter().map(|text| vec![Run::plain(text)]).collect()
+    }
+
+    #[test]
+    fn sizes_columns_to_their_content() {
+        let rows = [cells(&["a", "a much longer cell"]), cells(&["bb", "c"])];
+        let widths = column_widths(&Fonts::default(), rows.iter(), 2, 400.0, 12.0);
+        assert!(widths[0] < widths[1]);
+        assert!(widths.iter().sum::<f32>() < 400.0);
+
+        let long = "word ".repeat(60);
+        let rows = [cells(&["name", long.as_str()])];
+        let widths = column_widths(&Fonts::default(), rows.iter(), 2, 300.0, 12.0);
+        assert!((widths.iter().sum::<f32>() - 300.0).abs() < 0.01);
+        assert!(widths[0] >= longest_word(&Fonts::default(), &rows[0][0], 12.0) + 2.0 * CELL_PADDING);
+    }
+
+    #[test]
+    fn aligns_cells_and_bolds_the_header() {
+        let mut layout = Layout::default();
+        let table = Table {
+            alignments: vec![Align::Left, Align::Right],
+            header: cells(&["Name", "Count"]),
+            rows: vec![cells(&["apples", "3"]), cells(&["pears", "12"])],
+        };
+        layout.table(&table);
+        let lines = &layout.pages[0].lines;
+        assert_eq!(texts(&lines.iter().map(|line| line.spans.clone()).collect::<Vec<_>>()), vec![
+            "Name", "Count", "apples", "3", "pears", "12"
+        ]);
+        assert!(lines[0].spans[0].style.bold && !lines[2].spans[0].style.bold);
+        let right = |line: &Line| line.x + line.spans[0].width;
+        assert!((right(&lines[3]) - right(&lines[5])).abs() < 0.01);
+        assert_eq!(lines[2].x, lines[4].x);
+    }
+
+    #[test]
+    fn repeats_the_header_on_each_page() {
+        let mut layout = Layout::default();
+        let table = Table {
+            alignments: vec![Align::Left, Align::Center],
+            header: cells(&["Step", "Command"]),
+            rows: (0..100).map(|n| cells(&[&n.to_string(), "systemctl restart foo"])).collect(),
+        };
+        layout.table(&table);
+        assert!(layout.pages.len() > 1);
+        for page in &layout.pages {
+            assert_eq!(page.lines[0].spans[0].text, "Step");
+            assert!(page.lines.iter().all(|line| line.y < layout.bottom()));
+        }
+        let rows: usize = layout.pages.iter().map(|page| page.lines.len() / 2 - 1).sum();
+        assert_eq!(rows, 100);
+    }
+
+    #[test]
+    fn scales_images_down_to_the_text_width_and_captions_them() {
+        let mut layout = Layout::default();
+        layout.image(0, (200, 100), &[]);
+        layout.image(1, (4000, 1000), &[Run::plain("A wide chart")]);
+        let pictures = &layout.pages[0].pictures;
+        assert_eq!((pictures[0].width, pictures[0].height), (150.0, 75.0));
+        assert!((pictures[1].width - layout.theme.text_width()).abs() < 0.01);
+        assert!((pictures[1].width / pictures[1].height - 4.0).abs() < 0.01);
+        let caption = &layout.pages[0].lines[0];
+        assert_eq!(caption.spans[0].text, "A wide chart");
+        assert!(caption.spans[0].style.italic);
+        assert!(caption.y > pictures[1].y + pictures[1].height);
+    }
+
+    #[test]
+    fn anchors_headings_under_unique_slugs() {
+        let mut layout = Layout::default();
+        layout.heading(&[Run::plain("Getting started!")], 1);
+        layout.paragraph(&[Run::plain("text")]);
+        layout.heading(&[Run::plain("Getting "), Run::plain("started")], 2);
+        let slugs: Vec<&str> = layout.headings.iter().map(|heading| heading.slug.as_str()).collect();
+        assert_eq!(slugs, vec!["getting-started", "getting-started-1"]);
+        assert_eq!(layout.headings[1].title, "Getting started");
+        assert!(layout.anchors["getting-started-1"].y > layout.anchors["getting-started"].y);
+        assert_eq!(slug("Café & Crème 2.0"), "café--crème-20");
+    }
+
+    #[test]
+    fn prepending_pages_moves_positions_and_links_along() {
+        let mut body = Layout::default();
+        let link = body.link("https://example.com");
+        body.heading(&[Run::plain("Body")], 1);
+        let mut front = Layout::default();
+        let style = Style { link: Some(front.link("#body")), ..Style::default() };
+        front.paragraph(&[Run { text: "Body".to_string(), style }]);
+        front.new_page();
+        body.prepend(front);
+        assert_eq!(body.pages.len(), 3);
+        assert_eq!(body.headings[0].position.page, 2);
+        assert_eq!(body.anchors["body"].page, 2);
+        assert_eq!(body.links[link], "https://example.com");
+        let linked = body.pages[0].lines[0].spans[0].style.link.unwrap();
+        assert_eq!(body.links[linked], "#body");
+    }
+
+    #[test]
+    fn fills_in_running_heads_on_every_page() {
+        let header = Slots { left: "{title}".to_string(), right: "{date}".to_string(), ..Slots::default() };
+        let theme = Theme { page_size: PageSize::LETTER, header, ..Theme::default() };
+        let mut layout = Layout::new(&theme, Rc::default());
+        layout.paragraph(&[Run::plain("First page.")]);
+        layout.new_page();
+        layout.running_heads("Report", "2026-10-19");
+        let lines = &layout.pages[1].lines;
+        assert_eq!(texts(&lines.iter().map(|line| line.spans.clone()).collect::<Vec<_>>()), vec![
+            "Report", "2026-10-19", "2 / 2"
+        ]);
+        assert!(lines[0].y < theme.margins.top && lines[2].y > layout.bottom());
+        let right_edge = lines[1].x + lines[1].spans[0].width;
+        assert!((right_edge - (612.0 - theme.margins.right)).abs() < 0.01);
+    }
+
+    #[test]
+    fn sizes_headings_by_level() {
+        let mut layout = Layout::default();
+        layout.heading(&[Run::plain("Title")], 1);
+        layout.heading(&[Run::plain("Section")], 3);
+        let lines = &layout.pages[0].lines;
+        assert_eq!((lines[0].size, lines[1].size), (24.0, 16.0));
+        assert!(lines[0].spans[0].style.bold);
+    }
+
+    #[test]
+    fn adds_pages_when_the_current_one_is_full() {
+        let mut layout = Layout::default();
+        for _ in 0..60 {
+            layout.paragraph(&[Run::plain("A line of text.")]);
+        }
+        assert!(layout.pages.len() > 1);
+        for page in &layout.pages {
+            assert!(page.lines.iter().all(|line| line.y > layout.theme.margins.top && line.y < layout.bottom()));
+        }
+        let lines: usize = layout.pages.iter().map(|page| page.lines.len()).sum();
+        assert_eq!(lines, 60);
+    }
+
+    #[test]
+    fn keeps_a_heading_with_the_text_after_it() {
+        let mut layout = Layout::default();
+        while layout.cursor + 2.5 * layout.line_height(12.0) < layout.bottom() {
+            layout.paragraph(&[Run::plain("filler")]);
+        }
+        layout.heading(&[Run::plain("Next section")], 2);
+        assert_eq!(layout.pages.len(), 2);
+        assert_eq!(texts(&[layout.pages[1].lines[0].spans.clone()]), vec!["Next section"]);
+    }
+
+    #[test]
+    fn breaks_pages_only_after_something_is_set() {
+        let mut layout = Layout::default();
+        layout.page_break();
+        assert_eq!(layout.pages.len(), 1);
+        layout.paragraph(&[Run::plain("first")]);
+        layout.page_break();
+        layout.page_break();
+        layout.paragraph(&[Run::plain("second")]);
+        assert_eq!(layout.pages.len(), 2);
+        assert_eq!(layout.pages[1].lines[0].y, layout.pages[0].lines[0].y);
+    }
+
+    #[test]
+    fn hangs_list_markers_in_the_indent_of_nested_lists() {
+        let mut layout = Layout::default();
+        layout.start_list();
+        layout.list_marker("1.");
+        layout.paragraph(&[Run::plain("outer")]);
+        layout.start_list();
+        layout.list_marker("•");
+        layout.paragraph(&[Run::plain("inner")]);
+        layout.end_list();
+        layout.end_list();
+        layout.paragraph(&[Run::plain("after")]);
+        let lines = &layout.pages[0].lines;
+        assert_eq!(texts(&lines.iter().map(|line| line.spans.clone()).collect::<Vec<_>>()), vec!["1.", "outer", "•", "inner", "after"]);
+        let margin = layout.theme.margins.left;
+        assert_eq!((lines[1].x, lines[3].x, lines[4].x), (margin + LIST_INDENT, margin + 2.0 * LIST_INDENT, margin));
+        assert!(lines[0].x < lines[1].x && lines[0].y == lines[1].y);
+        assert!(lines[2].x > lines[1].x && lines[2].x < lines[3].x);
+    }
+
+    #[test]
+    fn draws_a_quote_bar_on_every_page_the_quote_reaches() {
+        let mut layout = Layout::default();
+        layout.paragraph(&[Run::plain("before")]);
+        layout.start_quote();
+        for _ in 0..40 {
+            layout.paragraph(&[Run::plain("quoted")]);
+        }
+        layout.end_quote();
+        layout.paragraph(&[Run::plain("after")]);
+        assert_eq!(layout.pages.len(), 2);
+        let margin = layout.theme.margins.left;
+        for page in &layout.pages {
+            assert_eq!(page.rules.len(), 1);
+            let bar = &page.rules[0];
+            assert_eq!(bar.from.0, margin + QUOTE_BAR / 2.0);
+            assert_eq!(bar.color, Some(layout.theme.quote_color.0));
+            let quoted: Vec<&Line> = page.lines.iter().filter(|line| line.spans[0].text == "quoted").collect();
+            assert!(quoted.iter().all(|line| line.x == margin + QUOTE_INDENT && line.y > bar.from.1 && line.y < bar.to.1));
+        }
+        let after = layout.pages[1].lines.last().unwrap();
+        assert!(after.x == margin && after.y > layout.pages[1].rules[0].to.1);
+    }
+}
@@ -0,0 +1,23 @@
+//! Sets Markdown as PDF, for the `markdown_to_pdf` command and for any other
+//! tool that wants to produce one.
+//!
+//! ```no_run
+//! use markdown_to_pdf::{render, RenderOptions};
+//!
+//! let pdf = render("# Weekly update\n\nAll on track.", &RenderOptions::default())?;
+//! std::fs::write("update.pdf", pdf)?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+mod contents;
+pub mod fonts;
+mod highlight;
+pub mod layout;
+pub mod metrics;
+mod navigation;
+mod pdf;
+mod render;
+mod subset;
+pub mod theme;
+
+pub use render::{layout, render, RenderOptions, Renderer, Source, Typeset, Warning};
@@ -1,153 +1,252 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::Parser;
-use pulldown_cmark::{Event, Options, Parser as MarkdownParser, Tag};
-use printpdf::*;
+use std::collections::HashMap;
 use std::fs;
-use std::io::BufWriter;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+mod inputs;
+
+use inputs::Input;
+use markdown_to_pdf::theme::{FontFiles, Margins, PageSize, Slots, Theme};
+use markdown_to_pdf::{RenderOptions, Renderer, Source, Warning};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Input markdown file
-    #[arg(short, long)]
-    input: PathBuf,
+    /// Markdown files, directories of them, or wildcard patterns such as "notes/*.md"; - reads standard input
+    #[arg(short, long, num_args = 1.., required = true)]
+    input: Vec<String>,
 
-    /// Output PDF file
+    /// Output PDF file, or - for standard output; with several inputs, the directory for their PDFs [default: beside each input]
     #[arg(short, long)]
-    output: PathBuf,
+    output: Option<PathBuf>,
+
+    /// Combine all the inputs into one PDF, each starting on a new page
+    #[arg(long)]
+    merge: bool,
+
+    /// Colour code blocks by the language named on their fence
+    #[arg(long)]
+    highlight: bool,
+
+    /// Start with a contents page, and give the PDF an outline of the headings
+    #[arg(long)]
+    toc: bool,
+
+    /// TOML theme setting the page, fonts, type sizes, spacing, colours, header and footer
+    #[arg(long)]
+    theme: Option<PathBuf>,
+
+    /// Page size: a4, a5, letter, legal, or WIDTHxHEIGHT such as 6x9in
+    #[arg(long)]
+    page_size: Option<PageSize>,
+
+    /// Margins, as in CSS: one, two or four lengths, such as "1in" or "25mm 20mm"
+    #[arg(long)]
+    margins: Option<Margins>,
+
+    /// Size of body text in points; the other sizes scale with it
+    #[arg(long)]
+    font_size: Option<f32>,
+
+    /// TrueType or OpenType font for the text, as REGULAR[,BOLD[,ITALIC[,BOLD_ITALIC]]] [default: Helvetica]
+    #[arg(long)]
+    font: Option<FontFiles>,
+
+    /// Font for code, in the same form as --font [default: Courier]
+    #[arg(long)]
+    mono_font: Option<FontFiles>,
+
+    /// Font for characters the text and code fonts lack; may be given more than once
+    #[arg(long)]
+    fallback_font: Vec<PathBuf>,
+
+    /// Running header, as TEXT or LEFT|CENTER|RIGHT; {title}, {date}, {page} and {pages} are filled in
+    #[arg(long)]
+    header: Option<Slots>,
+
+    /// Running footer, in the same form as --header
+    #[arg(long)]
+    footer: Option<Slots>,
+
+    /// Document title, for {title} and the PDF's metadata [default: the first top-level heading]
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Author, for the PDF's metadata
+    #[arg(long)]
+    author: Option<String>,
+
+    /// Date for {date} [default: today, in the theme's date_format]
+    #[arg(long)]
+    date: Option<String>,
+}
+
+/// The theme file, if there is one, with the command line's settings over it.
+fn theme(args: &Args) -> Result<Theme> {
+    let mut theme = match &args.theme {
+        Some(path) => Theme::from_file(path)?,
+        None => Theme::default(),
+    };
+    if let Some(page_size) = args.page_size {
+        theme.page_size = page_size;
+    }
+    if let Some(margins) = args.margins {
+        theme.margins = margins;
+    }
+    if let Some(font_size) = args.font_size {
+        theme.set_font_size(font_size);
+    }
+    if let Some(font) = &args.font {
+        theme.font = font.clone();
+    }
+    if let Some(mono_font) = &args.mono_font {
+        theme.mono_font = mono_font.clone();
+    }
+    if !args.fallback_font.is_empty() {
+        theme.fallback_fonts = args.fallback_font.clone();
+    }
+    if let Some(header) = &args.header {
+        theme.header = header.clone();
+    }
+    if let Some(footer) = &args.footer {
+        theme.footer = footer.clone();
+    }
+    Ok(theme)
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    let renderer = Renderer::new(RenderOptions {
+        theme: theme(&args)?,
+        highlight: args.highlight,
+        toc: args.toc,
+        title: args.title.clone(),
+        author: args.author.clone(),
+        date: args.date.clone(),
+    })?;
+    let inputs = inputs::expand(&args.input)?;
+    if args.merge || inputs.len() == 1 {
+        if inputs.len() > 1 && args.output.is_none() {
+            bail!("Name the merged PDF with --output");
+        }
+        return convert(&renderer, &inputs, &output(&inputs[0], args.output.as_deref())?);
+    }
 
-    // Read markdown file
-    let markdown_content = fs::read_to_string(&args.input)
-        .with_context(|| format!("Failed to read input file: {}", args.input.display()))?;
-
-    // Parse markdown
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_FOOTNOTES);
-    options.insert(Options::ENABLE_TASKLISTS);
-    options.insert(Options::ENABLE_SMART_PUNCTUATION);
-    
-    let parser = MarkdownParser::new_ext(&markdown_content, options);
-
-    // Create PDF document
-    let (doc, page1, layer1) = PdfDocument::new("Markdown Document", 
-        Mm(210.0), // A4 width
-        Mm(297.0), // A4 height
-        "Layer 1"
-    );
-
-    // Create a new layer for the content
-    let current_layer = doc.get_page(page1).get_layer(layer1);
-
-    // Add text to the PDF with proper formatting
-    let font = doc.add_builtin_font(BuiltinFont::Helvetica)?;
-    let bold_font = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
-    
-    let mut y_pos = Mm(287.0); // Start from top with margin
-    let line_height = Mm(14.0);
-    let mut list_indent: u32 = 0;
-    let mut current_text = String::new();
-    let mut is_bold = false;
-    let mut list_counter = 0;
-    let mut is_ordered_list = false;
-    let mut in_list_item = false;
-
-    for event in parser {
-        match event {
-            Event::Start(tag) => {
-                match tag {
-                    Tag::Heading(level, _, _) => {
-                        if !current_text.is_empty() {
-                            current_layer.use_text(&current_text, 12.0, Mm(10.0), y_pos, &font);
-                            current_text.clear();
-                            y_pos -= line_height;
-                        }
-                    }
-                    Tag::Strong => {
-                        is_bold = true;
-                    }
-                    Tag::List(Some(_)) => {
-                        is_ordered_list = true;
-                        list_indent += 1;
-                        list_counter = 0;
-                    }
-                    Tag::List(None) => {
-                        is_ordered_list = false;
-                        list_indent += 1;
-                    }
-                    Tag::Item => {
-                        in_list_item = true;
-                        if !current_text.is_empty() {
-                            let indent = Mm(10.0 + (list_indent as f32 * 10.0));
-                            let prefix = if is_ordered_list {
-                                list_counter += 1;
-                                format!("{}. ", list_counter)
-                            } else {
-                                "• ".to_string()
-                            };
-                            current_layer.use_text(&format!("{}{}", prefix, current_text), 12.0, indent, y_pos, &font);
-                            current_text.clear();
-                            y_pos -= line_height;
-                        }
-                    }
-                    _ => {}
-                }
-            }
-            Event::End(tag) => {
-                match tag {
-                    Tag::Paragraph => {
-                        if !current_text.is_empty() && !in_list_item {
-                            current_layer.use_text(&current_text, 12.0, Mm(10.0), y_pos, &font);
-                            current_text.clear();
-                            y_pos -= line_height;
-                        }
-                    }
-                    Tag::Strong => {
-                        is_bold = false;
-                    }
-                    Tag::List(_) => {
-                        list_indent = list_indent.saturating_sub(1);
-                    }
-                    Tag::Item => {
-                        in_list_item = false;
-                    }
-                    _ => {}
-                }
-            }
-            Event::Text(text) => {
-                current_text.push_str(&text);
-            }
-            Event::SoftBreak => {
-                current_text.push(' ');
-            }
-            Event::HardBreak => {
-                if !current_text.is_empty() {
-                    current_layer.use_text(&current_text, 12.0, Mm(10.0), y_pos, &font);
-                    current_text.clear();
-                    y_pos -= line_height;
-                }
-            }
-            Event::TaskListMarker(checked) => {
-                let marker = if checked { "☒ " } else { "☐ " };
-                current_text.push_str(marker);
+    if inputs.contains(&Input::Stdin) {
+        bail!("Standard input can only be converted on its own or with --merge");
+    }
+    if let Some(dir) = &args.output {
+        if dir == Path::new("-") {
+            bail!("Several PDFs can't all go to standard output; use --merge to combine them");
+        }
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    }
+    let outputs = batch_outputs(&inputs, args.output.as_deref())?;
+    let mut failed = 0;
+    for (input, output) in inputs.iter().zip(&outputs) {
+        if let Err(error) = convert(&renderer, std::slice::from_ref(input), output) {
+            eprintln!("Error: {}: {:#}", input.name(), error);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        bail!("{} of {} files failed to convert", failed, inputs.len());
+    }
+    Ok(())
+}
+
+/// Where a PDF goes.
+#[derive(Debug)]
+enum Output {
+    Stdout,
+    File(PathBuf),
+}
+
+impl Output {
+    fn write(&self, pdf: &[u8]) -> Result<()> {
+        match self {
+            Output::Stdout => io::stdout().write_all(pdf).context("Failed to write the PDF to standard output"),
+            Output::File(path) => {
+                fs::write(path, pdf).with_context(|| format!("Failed to save PDF to: {}", path.display()))
             }
-            _ => {}
         }
     }
+}
 
-    // Save the PDF
-    let file = fs::File::create(&args.output)
-        .with_context(|| format!("Failed to create output file: {}", args.output.display()))?;
-    let mut writer = BufWriter::new(file);
-    doc.save(&mut writer)
-        .with_context(|| format!("Failed to save PDF to: {}", args.output.display()))?;
+/// Where the PDF made from `input` goes: to `output`, or into it if it is a
+/// directory. Without `output`, a file's PDF goes beside it and standard
+/// input's to standard output.
+fn output(input: &Input, output: Option<&Path>) -> Result<Output> {
+    match (input, output) {
+        (_, Some(path)) if path == Path::new("-") => Ok(Output::Stdout),
+        (_, Some(path)) if !path.is_dir() => Ok(Output::File(path.to_path_buf())),
+        (Input::File(path), Some(dir)) => Ok(Output::File(dir.join(path.with_extension("pdf").file_name().unwrap_or_default()))),
+        (Input::File(path), None) => Ok(Output::File(path.with_extension("pdf"))),
+        (Input::Stdin, None) => Ok(Output::Stdout),
+        (Input::Stdin, Some(dir)) => bail!("{} is a directory; give the PDF made from standard input a file name", dir.display()),
+    }
+}
+
+/// Where each of `inputs` goes when converted one by one. Fails before
+/// anything is written if two would end up at the same path, such as
+/// `a/readme.md` and `b/readme.md` sent into one directory.
+fn batch_outputs(inputs: &[Input], dir: Option<&Path>) -> Result<Vec<Output>> {
+    let outputs = inputs.iter().map(|input| output(input, dir)).collect::<Result<Vec<Output>>>()?;
+    let mut sources: HashMap<&Path, &Input> = HashMap::new();
+    for (input, output) in inputs.iter().zip(&outputs) {
+        if let Output::File(path) = output
+            && let Some(other) = sources.insert(path, input)
+        {
+            bail!(
+                "{} and {} would both be saved as {}; convert them separately or combine them with --merge",
+                other.name(),
+                input.name(),
+                path.display()
+            );
+        }
+    }
+    Ok(outputs)
+}
 
-    println!("Successfully converted {} to {}", args.input.display(), args.output.display());
+/// Converts `inputs` into one PDF, each starting on a new page, and writes
+/// it to `output`.
+fn convert(renderer: &Renderer, inputs: &[Input], output: &Output) -> Result<()> {
+    let sources = inputs
+        .iter()
+        .map(|input| {
+            Ok(Source { markdown: input.read()?, base_dir: input.base_dir().to_path_buf(), name: input.stem() })
+        })
+        .collect::<Result<Vec<Source>>>()?;
+    let typeset = renderer.typeset(&sources)?;
+    for warning in &typeset.warnings {
+        match warning {
+            Warning::MissingGlyphs(_) => eprintln!("Warning: {}; add one with --fallback-font", warning),
+            _ => eprintln!("Warning: {}", warning),
+        }
+    }
+    output.write(&renderer.draw(&typeset)?)?;
+
+    if let Output::File(path) = output {
+        let names: Vec<String> = inputs.iter().map(Input::name).collect();
+        println!("Successfully converted {} to {}", names.join(", "), path.display());
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_batches_that_would_overwrite_a_pdf() {
+        let inputs = [Input::File(PathBuf::from("a/readme.md")), Input::File(PathBuf::from("b/readme.md"))];
+        assert_eq!(batch_outputs(&inputs, None).unwrap().len(), 2);
+        let error = batch_outputs(&inputs, Some(Path::new("."))).unwrap_err();
+        assert!(error.to_string().starts_with("a/readme.md and b/readme.md would both be saved as ./readme.pdf"));
+
+        let inputs = [Input::File(PathBuf::from("a/notes.md")), Input::File(PathBuf::from("a/notes.markdown"))];
+        assert!(batch_outputs(&inputs, None).is_err());
+    }
+}
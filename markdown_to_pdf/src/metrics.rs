@@ -0,0 +1,134 @@
+//! Advance widths of the builtin PDF fonts, in thousandths of the font size,
+//! taken from Adobe's AFM files. PDF viewers supply the glyphs for these
+//! fonts, so the widths are all we need to lay text out ourselves.
+
+/// The builtin fonts the renderer uses. Each oblique face has the same
+/// widths as its upright one, and every Courier glyph is 600 units wide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Font {
+    Helvetica,
+    HelveticaBold,
+    HelveticaOblique,
+    HelveticaBoldOblique,
+    Courier,
+    CourierBold,
+    CourierOblique,
+    CourierBoldOblique,
+}
+
+impl Font {
+    pub fn select(bold: bool, italic: bool, mono: bool) -> Font {
+        match (mono, bold, italic) {
+            (false, false, false) => Font::Helvetica,
+            (false, true, false) => Font::HelveticaBold,
+            (false, false, true) => Font::HelveticaOblique,
+            (false, true, true) => Font::HelveticaBoldOblique,
+            (true, false, false) => Font::Courier,
+            (true, true, false) => Font::CourierBold,
+            (true, false, true) => Font::CourierOblique,
+            (true, true, true) => Font::CourierBoldOblique,
+        }
+    }
+}
+
+/// Helvetica, for the printable ASCII characters `' '..='~'`.
+const HELVETICA: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, // space to /
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, // 0 to ?
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778, // @ to O
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, // P to _
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, // ` to o
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584, // p to ~
+];
+
+/// Helvetica-Bold, for the printable ASCII characters `' '..='~'`.
+const HELVETICA_BOLD: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, // space to /
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, // 0 to ?
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778, // @ to O
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556, // P to _
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611, // ` to o
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584, // p to ~
+];
+
+const COURIER: u16 = 600;
+
+/// Characters the builtin fonts can show besides printable ASCII and
+/// Latin-1: the rest of WinAnsiEncoding, which is how their text is encoded.
+const WIN_ANSI_EXTRA: &str = "€‚ƒ„…†‡ˆ‰Š‹ŒŽ‘’“”•–—˜™š›œžŸ";
+
+/// Whether the builtin fonts have a glyph for `c`.
+pub fn builtin_covers(c: char) -> bool {
+    matches!(c, ' '..='~' | '\u{a0}'..='\u{ff}') || WIN_ANSI_EXTRA.contains(c)
+}
+
+/// The letter an accented Latin-1 letter is as wide as in Helvetica.
+fn unaccented(c: char) -> Option<char> {
+    Some(match c {
+        'À'..='Å' => 'A',
+        'Ç' => 'C',
+        'È'..='Ë' => 'E',
+        'Ì'..='Ï' => 'I',
+        'Ñ' => 'N',
+        'Ò'..='Ö' => 'O',
+        'Ù'..='Ü' => 'U',
+        'Ý' => 'Y',
+        'à'..='å' => 'a',
+        'ç' => 'c',
+        'è'..='ë' => 'e',
+        'ñ' => 'n',
+        'ò'..='ö' => 'o',
+        'ù'..='ü' => 'u',
+        'ý' | 'ÿ' => 'y',
+        _ => return None,
+    })
+}
+
+/// Width of the characters outside ASCII that the Markdown itself tends to
+/// produce (smart punctuation, bullets), falling back to an average letter.
+fn helvetica_extra(c: char, bold: bool) -> u16 {
+    match c {
+        // Dotless, so wider than a plain i.
+        'ì'..='ï' => 278,
+        '\u{2018}' | '\u{2019}' | '\u{201a}' => if bold { 278 } else { 222 },
+        '\u{201c}' | '\u{201d}' | '\u{201e}' => if bold { 500 } else { 333 },
+        '\u{2013}' => 556,
+        '\u{2014}' | '\u{2026}' | '\u{2030}' => 1000,
+        '\u{2022}' => 350,
+        '\u{a0}' => 278,
+        _ => if bold { 611 } else { 556 },
+    }
+}
+
+/// Width of `c` in `font`, in thousandths of the font size.
+pub fn char_width(font: Font, c: char) -> u16 {
+    let bold = match font {
+        Font::Helvetica | Font::HelveticaOblique => false,
+        Font::HelveticaBold | Font::HelveticaBoldOblique => true,
+        _ => return COURIER,
+    };
+    match (c, bold) {
+        (' '..='~', false) => HELVETICA[c as usize - ' ' as usize],
+        (' '..='~', true) => HELVETICA_BOLD[c as usize - ' ' as usize],
+        _ => match unaccented(c) {
+            Some(letter) => char_width(font, letter),
+            None => helvetica_extra(c, bold),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_each_face() {
+        assert_eq!(char_width(Font::Helvetica, 'W'), 944);
+        assert_eq!(char_width(Font::HelveticaOblique, 'i'), 222);
+        assert_eq!(char_width(Font::HelveticaBoldOblique, 'i'), 278);
+        assert_eq!(char_width(Font::CourierBold, 'i'), 600);
+        assert_eq!(char_width(Font::Helvetica, '\u{2014}'), 1000);
+        assert_eq!(char_width(Font::HelveticaBold, 'é'), char_width(Font::HelveticaBold, 'e'));
+        assert!(builtin_covers('é') && builtin_covers('•') && !builtin_covers('☐'));
+    }
+}
@@ -0,0 +1,213 @@
+//! Clickable links and the document outline (the bookmarks sidebar).
+//!
+//! printpdf can only make links that open a URI, and gives each page at
+//! most one bookmark, so both are added to the finished PDF with lopdf: a
+//! link annotation over every span of link text, and an outline nested the
+//! way the headings are. The title and author printpdf put in the document
+//! information are re-encoded on the way, since it writes them as raw UTF-8,
+//! which viewers misread unless the text is ASCII.
+
+use anyhow::{Context, Result};
+use printpdf::lopdf::{Dictionary, Document, Object, ObjectId, StringFormat};
+
+use crate::layout::{Layout, Position, ASCENT};
+
+/// Adds links, and an outline if `outline` is set, to `pdf`, which must be
+/// `layout` as drawn by `pdf::draw`.
+pub fn add(pdf: &[u8], layout: &Layout, outline: bool) -> Result<Vec<u8>> {
+    let mut doc = Document::load_mem(pdf).context("Failed to reread the PDF to add links")?;
+    let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    add_links(&mut doc, &pages, layout)?;
+    encode_info(&mut doc);
+    if outline && !layout.headings.is_empty() {
+        add_outline(&mut doc, &pages, layout)?;
+    }
+    let mut bytes = Vec::new();
+    doc.save_to(&mut bytes).context("Failed to write the PDF")?;
+    Ok(bytes)
+}
+
+/// An explicit destination: the page, scrolled so `position` is at the top.
+fn destination(pages: &[ObjectId], page_height: f32, position: Position) -> Object {
+    Object::Array(vec![
+        Object::Reference(pages[position.page]),
+        Object::Name(b"XYZ".to_vec()),
+        Object::Null,
+        (page_height - position.y).into(),
+        Object::Null,
+    ])
+}
+
+/// A PDF text string: plain bytes for ASCII, otherwise UTF-16 with a BOM.
+fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::String(text.as_bytes().to_vec(), StringFormat::Literal);
+    }
+    let mut bytes = vec![0xfe, 0xff];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+/// Rewrites the text strings in the document information dictionary as PDF
+/// text strings.
+fn encode_info(doc: &mut Document) {
+    let Ok(id) = doc.trailer.get(b"Info").and_then(Object::as_reference) else { return };
+    let Ok(info) = doc.get_dictionary_mut(id) else { return };
+    for key in [b"Title".as_slice(), b"Author", b"Subject", b"Keywords"] {
+        let text = match info.get(key) {
+            Ok(Object::String(bytes, _)) => String::from_utf8(bytes.clone()).ok(),
+            _ => None,
+        };
+        if let Some(text) = text.filter(|text| !text.is_ascii()) {
+            info.set(key, text_string(&text));
+        }
+    }
+}
+
+fn add_links(doc: &mut Document, pages: &[ObjectId], layout: &Layout) -> Result<()> {
+    let page_height = layout.theme.page_size.height;
+    for (page, page_id) in layout.pages.iter().zip(pages) {
+        let mut annotations = Vec::new();
+        for line in &page.lines {
+            for span in &line.spans {
+                let Some(link) = span.style.link else { continue };
+                let target = &layout.links[link];
+                let mut annotation = Dictionary::new();
+                annotation.set("Type", Object::Name(b"Annot".to_vec()));
+                annotation.set("Subtype", Object::Name(b"Link".to_vec()));
+                let x = line.x + span.x;
+                let top = page_height - (line.y - line.size * ASCENT);
+                let bottom = page_height - (line.y + line.size * (1.0 - ASCENT));
+                annotation.set("Rect", vec![x.into(), bottom.into(), (x + span.width).into(), top.into()]);
+                annotation.set("Border", vec![0.into(), 0.into(), 0.into()]);
+                match target.strip_prefix('#') {
+                    Some(anchor) => match layout.anchors.get(anchor) {
+                        Some(position) => annotation.set("Dest", destination(pages, page_height, *position)),
+                        None => continue,
+                    },
+                    None => {
+                        let mut action = Dictionary::new();
+                        action.set("S", Object::Name(b"URI".to_vec()));
+                        action.set("URI", Object::string_literal(target.as_str()));
+                        annotation.set("A", action);
+                    },
+                }
+                annotations.push(Object::Reference(doc.add_object(annotation)));
+            }
+        }
+        if !annotations.is_empty() {
+            doc.get_dictionary_mut(*page_id)?.set("Annots", annotations);
+        }
+    }
+    Ok(())
+}
+
+/// Builds the outline from the headings, each one a child of the nearest
+/// heading before it with a lower level.
+fn add_outline(doc: &mut Document, pages: &[ObjectId], layout: &Layout) -> Result<()> {
+    let headings = &layout.headings;
+    let root = doc.new_object_id();
+    let ids: Vec<ObjectId> = headings.iter().map(|_| doc.new_object_id()).collect();
+    let parents: Vec<Option<usize>> = (0..headings.len())
+        .map(|index| (0..index).rev().find(|&before| headings[before].level < headings[index].level))
+        .collect();
+    let children = |parent: Option<usize>| -> Vec<usize> {
+        (0..headings.len()).filter(|&index| parents[index] == parent).collect()
+    };
+    let descendants = |index: usize| -> i64 {
+        let mut count = 0;
+        let mut next = index + 1;
+        while next < headings.len() && headings[next].level > headings[index].level {
+            count += 1;
+            next += 1;
+        }
+        count
+    };
+    let link_siblings = |dict: &mut Dictionary, siblings: &[usize], at: usize| {
+        if at > 0 {
+            dict.set("Prev", Object::Reference(ids[siblings[at - 1]]));
+        }
+        if at + 1 < siblings.len() {
+            dict.set("Next", Object::Reference(ids[siblings[at + 1]]));
+        }
+    };
+
+    let mut items = Vec::new();
+    for (index, heading) in headings.iter().enumerate() {
+        let mut item = Dictionary::new();
+        item.set("Title", text_string(&heading.title));
+        item.set("Parent", Object::Reference(parents[index].map_or(root, |parent| ids[parent])));
+        item.set("Dest", destination(pages, layout.theme.page_size.height, heading.position));
+        let siblings = children(parents[index]);
+        let at = siblings.iter().position(|&sibling| sibling == index).unwrap();
+        link_siblings(&mut item, &siblings, at);
+        let own = children(Some(index));
+        if let (Some(first), Some(last)) = (own.first(), own.last()) {
+            item.set("First", Object::Reference(ids[*first]));
+            item.set("Last", Object::Reference(ids[*last]));
+            item.set("Count", descendants(index));
+        }
+        items.push(item);
+    }
+    for (id, item) in ids.iter().zip(items) {
+        doc.objects.insert(*id, Object::Dictionary(item));
+    }
+
+    let top = children(None);
+    let mut outlines = Dictionary::new();
+    outlines.set("Type", Object::Name(b"Outlines".to_vec()));
+    outlines.set("First", Object::Reference(ids[top[0]]));
+    outlines.set("Last", Object::Reference(ids[*top.last().unwrap()]));
+    outlines.set("Count", headings.len() as i64);
+    doc.objects.insert(root, Object::Dictionary(outlines));
+
+    let catalog = doc.catalog_mut()?;
+    catalog.set("Outlines", Object::Reference(root));
+    catalog.set("PageMode", Object::Name(b"UseOutlines".to_vec()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{Run, Style};
+
+    fn dictionary<'a>(doc: &'a Document, object: &Object) -> &'a Dictionary {
+        doc.get_dictionary(object.as_reference().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn adds_link_annotations_and_a_nested_outline() {
+        let mut layout = Layout::default();
+        layout.heading(&[Run::plain("Intro")], 1);
+        let site = Style { link: Some(layout.link("https://example.com")), ..Style::default() };
+        let back = Style { link: Some(layout.link("#intro")), ..Style::default() };
+        let missing = Style { link: Some(layout.link("#nowhere")), ..Style::default() };
+        layout.paragraph(&[
+            Run { text: "site".to_string(), style: site },
+            Run::plain(" "),
+            Run { text: "back".to_string(), style: back },
+            Run::plain(" "),
+            Run { text: "lost".to_string(), style: missing },
+        ]);
+        layout.heading(&[Run::plain("Détails")], 2);
+        let pdf = crate::pdf::draw(&layout, &[], "Test", None).unwrap().save_to_bytes().unwrap();
+        let doc = Document::load_mem(&add(&pdf, &layout, true).unwrap()).unwrap();
+
+        let page = doc.get_pages()[&1];
+        let annotations = doc.get_dictionary(page).unwrap().get(b"Annots").unwrap().as_array().unwrap();
+        assert_eq!(annotations.len(), 2);
+        let uri = dictionary(&doc, &annotations[0]).get(b"A").unwrap().as_dict().unwrap();
+        assert_eq!(uri.get(b"URI").unwrap().as_str().unwrap(), b"https://example.com");
+        let dest = dictionary(&doc, &annotations[1]).get(b"Dest").unwrap().as_array().unwrap();
+        assert_eq!(dest[0].as_reference().unwrap(), page);
+
+        let outlines = dictionary(&doc, doc.catalog().unwrap().get(b"Outlines").unwrap());
+        let intro = dictionary(&doc, outlines.get(b"First").unwrap());
+        assert_eq!(intro.get(b"Title").unwrap().as_str().unwrap(), b"Intro");
+        assert_eq!(intro.get(b"Count").unwrap().as_i64().unwrap(), 1);
+        let details = dictionary(&doc, intro.get(b"First").unwrap());
+        let title: Vec<u8> = [0xfe, 0xff].into_iter().chain("Détails".encode_utf16().flat_map(u16::to_be_bytes)).collect();
+        assert_eq!(details.get(b"Title").unwrap().as_str().unwrap(), title.as_slice());
+    }
+}
@@ -0,0 +1,153 @@
+//! Draws a finished `Layout` into a PDF document.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use printpdf::image_crate::{self, DynamicImage};
+use printpdf::*;
+
+use crate::fonts::Face;
+use crate::layout::Layout;
+use crate::metrics::Font;
+use crate::subset::subset;
+
+fn builtin(font: Font) -> BuiltinFont {
+    match font {
+        Font::Helvetica => BuiltinFont::Helvetica,
+        Font::HelveticaBold => BuiltinFont::HelveticaBold,
+        Font::HelveticaOblique => BuiltinFont::HelveticaOblique,
+        Font::HelveticaBoldOblique => BuiltinFont::HelveticaBoldOblique,
+        Font::Courier => BuiltinFont::Courier,
+        Font::CourierBold => BuiltinFont::CourierBold,
+        Font::CourierOblique => BuiltinFont::CourierOblique,
+        Font::CourierBoldOblique => BuiltinFont::CourierBoldOblique,
+    }
+}
+
+fn color(rgb: [u8; 3]) -> Color {
+    Color::Rgb(Rgb::new(rgb[0] as f32 / 255.0, rgb[1] as f32 / 255.0, rgb[2] as f32 / 255.0, None))
+}
+
+/// A point given from the top of a page `height` points high.
+fn point(height: f32, (x, y): (f32, f32)) -> Point {
+    Point::new(Mm::from(Pt(x)), Mm::from(Pt(height - y)))
+}
+
+fn rule(layer: &PdfLayerReference, height: f32, from: (f32, f32), to: (f32, f32), thickness: f32) {
+    layer.set_outline_thickness(thickness);
+    let points = vec![(point(height, from), false), (point(height, to), false)];
+    layer.add_line(printpdf::Line { points, is_closed: false });
+}
+
+/// Reads a PNG or JPEG, in a form printpdf can embed: 8 bits a channel,
+/// with an alpha channel only if the image has one.
+pub fn load_image(path: &Path) -> Result<DynamicImage> {
+    let image = image_crate::open(path).with_context(|| format!("Failed to read image: {}", path.display()))?;
+    Ok(if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    })
+}
+
+/// Adds the fonts `layout` uses to `doc`. Font files are cut down to the
+/// glyphs the text needs, or embedded whole if they can't be.
+fn add_fonts(doc: &PdfDocumentReference, layout: &Layout) -> Result<HashMap<Face, IndirectFontRef>> {
+    let mut chars: BTreeMap<Face, BTreeMap<char, u16>> = BTreeMap::new();
+    for span in layout.pages.iter().flat_map(|page| &page.lines).flat_map(|line| &line.spans) {
+        let used = chars.entry(span.face).or_default();
+        if let Face::File(index) = span.face {
+            let file = &layout.fonts.files[index];
+            used.extend(span.text.chars().filter_map(|c| Some((c, file.glyph(c)?))));
+        }
+    }
+    let mut fonts = HashMap::new();
+    for (face, used) in chars {
+        let font = match face {
+            Face::Builtin(font) => doc.add_builtin_font(builtin(font))?,
+            Face::File(index) => {
+                let file = &layout.fonts.files[index];
+                let data = subset(file.data(), &used).unwrap_or_else(|| file.data().to_vec());
+                doc.add_external_font(Cursor::new(data))
+                    .with_context(|| format!("Failed to embed font: {}", file.path.display()))?
+            },
+        };
+        fonts.insert(face, font);
+    }
+    Ok(fonts)
+}
+
+/// Draws `layout` into a new document; `images` are the images its pictures
+/// refer to.
+pub fn draw(layout: &Layout, images: &[DynamicImage], title: &str, author: Option<&str>) -> Result<PdfDocumentReference> {
+    let theme = &layout.theme;
+    let (width, height) = (theme.page_size.width, theme.page_size.height);
+    let (mut doc, first_page, first_layer) =
+        PdfDocument::new(title, Mm::from(Pt(width)), Mm::from(Pt(height)), "Layer 1");
+    if let Some(author) = author {
+        doc = doc.with_author(author);
+    }
+    let fonts = add_fonts(&doc, layout)?;
+
+    for (index, page) in layout.pages.iter().enumerate() {
+        let layer = if index == 0 {
+            doc.get_page(first_page).get_layer(first_layer)
+        } else {
+            let (page, layer) = doc.add_page(Mm::from(Pt(width)), Mm::from(Pt(height)), "Layer 1");
+            doc.get_page(page).get_layer(layer)
+        };
+        layer.set_fill_color(color(theme.code_background.0));
+        for shade in &page.shading {
+            let top = height - shade.y;
+            layer.add_rect(Rect::new(
+                Mm::from(Pt(shade.x)),
+                Mm::from(Pt(top - shade.height)),
+                Mm::from(Pt(shade.x + shade.width)),
+                Mm::from(Pt(top)),
+            ));
+        }
+        for picture in &page.pictures {
+            let image = &images[picture.image];
+            // At 72 dpi printpdf draws a pixel one point across.
+            let transform = ImageTransform {
+                translate_x: Some(Mm::from(Pt(picture.x))),
+                translate_y: Some(Mm::from(Pt(height - picture.y - picture.height))),
+                scale_x: Some(picture.width / image.width() as f32),
+                scale_y: Some(picture.height / image.height() as f32),
+                dpi: Some(72.0),
+                ..ImageTransform::default()
+            };
+            Image::from_dynamic_image(image).add_to_layer(layer.clone(), transform);
+        }
+        for page_rule in &page.rules {
+            layer.set_outline_color(color(page_rule.color.unwrap_or(theme.text_color.0)));
+            rule(&layer, height, page_rule.from, page_rule.to, page_rule.thickness);
+        }
+        let mut fill = theme.code_background.0;
+        for line in &page.lines {
+            for span in &line.spans {
+                let x = line.x + span.x;
+                let text_color = span.style.color.unwrap_or(theme.text_color.0);
+                if text_color != fill {
+                    layer.set_fill_color(color(text_color));
+                    fill = text_color;
+                }
+                layer.use_text(
+                    span.text.as_str(),
+                    line.size,
+                    Mm::from(Pt(x)),
+                    Mm::from(Pt(height - line.y)),
+                    &fonts[&span.face],
+                );
+                if span.style.strike {
+                    let y = line.y - line.size * 0.3;
+                    layer.set_outline_color(color(text_color));
+                    rule(&layer, height, (x, y), (x + span.width, y), line.size * 0.06);
+                }
+            }
+        }
+    }
+    Ok(doc)
+}
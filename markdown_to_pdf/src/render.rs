@@ -0,0 +1,581 @@
+//! Turns Markdown into a PDF: reads the Markdown events, sets them with
+//! `Layout`, and draws the pages.
+//!
+//! `layout` stops at the laid-out pages, which is what the tests look at;
+//! `render` goes on to the PDF's bytes. A `Renderer` does the same for
+//! several documents at once, and keeps its fonts for the next call.
+//! Nothing is printed: what didn't come out as written is returned as
+//! `Warning`s in the `Typeset`, for the caller to report.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+use printpdf::image_crate::DynamicImage;
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, Options, Parser as MarkdownParser, Tag};
+
+use crate::contents;
+use crate::fonts::Fonts;
+use crate::highlight::{self, Highlighter};
+use crate::layout::{push_text, Align, Layout, Run, Style, Table};
+use crate::navigation;
+use crate::pdf;
+use crate::theme::Theme;
+
+#[derive(Debug, Default, Clone)]
+pub struct RenderOptions {
+    pub theme: Theme,
+    /// Colour code blocks by the language named on their fence.
+    pub highlight: bool,
+    /// Start with a contents page, and give the PDF an outline of the headings.
+    pub toc: bool,
+    /// The title for `{title}` and the PDF's metadata; the first top-level
+    /// heading if `None`.
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// The date for `{date}`; today, in the theme's `date_format`, if `None`.
+    pub date: Option<String>,
+}
+
+/// A Markdown document to render.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub markdown: String,
+    /// Where the document's images are looked for.
+    pub base_dir: PathBuf,
+    /// The document's name, such as its file name without the extension,
+    /// for the title if it has no top-level heading.
+    pub name: Option<String>,
+}
+
+impl Source {
+    pub fn new(markdown: &str) -> Source {
+        Source { markdown: markdown.to_string(), base_dir: PathBuf::from("."), name: None }
+    }
+}
+
+/// Something that didn't come out as written, though the pages were still set.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// An image that couldn't be embedded, and why; its alt text stands in for it.
+    Image(String),
+    /// The label of a footnote that is referred to but never defined.
+    UndefinedFootnote(String),
+    /// Characters no font has a glyph for, which come out blank.
+    MissingGlyphs(String),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::Image(reason) => write!(f, "{}", reason),
+            Warning::UndefinedFootnote(label) => write!(f, "footnote [^{}] is referred to but never defined", label),
+            Warning::MissingGlyphs(missing) => write!(f, "no font has a glyph for {:?}", missing),
+        }
+    }
+}
+
+/// Pages ready to be drawn, and what drawing them needs.
+#[derive(Debug)]
+pub struct Typeset {
+    pub layout: Layout,
+    /// The images the layout's pictures refer to.
+    pub images: Vec<DynamicImage>,
+    pub title: String,
+    pub warnings: Vec<Warning>,
+}
+
+/// Lays out `markdown`, as `render` would draw it.
+pub fn layout(markdown: &str, options: &RenderOptions) -> Result<Layout> {
+    Ok(Renderer::new(options.clone())?.typeset(&[Source::new(markdown)])?.layout)
+}
+
+/// Renders `markdown` as a PDF, without its warnings; `Renderer::typeset`
+/// returns those.
+pub fn render(markdown: &str, options: &RenderOptions) -> Result<Vec<u8>> {
+    Renderer::new(options.clone())?.render(&[Source::new(markdown)])
+}
+
+/// Renders with the same options, and the fonts they name, over and over.
+pub struct Renderer {
+    options: RenderOptions,
+    fonts: Rc<Fonts>,
+    highlighter: Option<Highlighter>,
+}
+
+impl Renderer {
+    /// Loads the fonts `options.theme` names.
+    pub fn new(options: RenderOptions) -> Result<Renderer> {
+        let theme = &options.theme;
+        theme.validate()?;
+        if theme.text_width() <= 0.0 || theme.margins.top + theme.margins.bottom >= theme.page_size.height {
+            bail!("The margins leave no room on the page");
+        }
+        Ok(Renderer {
+            fonts: Rc::new(Fonts::load(theme)?),
+            highlighter: options.highlight.then(Highlighter::new),
+            options,
+        })
+    }
+
+    /// Lays out `sources` one after another, each starting on a new page,
+    /// with the contents page and running heads if the options ask for them.
+    pub fn typeset(&self, sources: &[Source]) -> Result<Typeset> {
+        let mut layout = Layout::new(&self.options.theme, self.fonts.clone());
+        let mut images = Vec::new();
+        let mut warnings = Vec::new();
+        // Each source's first top-level heading, or else its name.
+        let mut titles = Vec::new();
+        for (index, source) in sources.iter().enumerate() {
+            layout.page_break();
+            let first_heading = layout.headings.len();
+            let markdown = &source.markdown;
+            let highlighter = self.highlighter.as_ref();
+            read_markdown(&mut layout, &mut images, &mut warnings, markdown, &source.base_dir, highlighter, index);
+            let heading = layout.headings[first_heading..].iter().find(|heading| heading.level == 1);
+            titles.push(heading.map(|heading| heading.title.clone()).or_else(|| source.name.clone()));
+        }
+        let title = self.options.title.clone().or_else(|| titles.into_iter().flatten().next()).unwrap_or_default();
+        if self.options.toc {
+            let contents = contents::contents(&layout);
+            layout.prepend(contents);
+        }
+        let date = self
+            .options
+            .date
+            .clone()
+            .unwrap_or_else(|| chrono::Local::now().format(&self.options.theme.date_format).to_string());
+        layout.running_heads(&title, &date);
+        warnings.extend(missing_glyphs(&layout));
+        Ok(Typeset { layout, images, title, warnings })
+    }
+
+    /// Draws pages set by `typeset` as a PDF.
+    pub fn draw(&self, typeset: &Typeset) -> Result<Vec<u8>> {
+        let Typeset { layout, images, title, .. } = typeset;
+        let doc = pdf::draw(layout, images, title, self.options.author.as_deref())?;
+        let pdf = doc.save_to_bytes().context("Failed to write the PDF")?;
+        navigation::add(&pdf, layout, self.options.toc)
+    }
+
+    /// Renders `sources` as one PDF, each starting on a new page, without
+    /// the warnings; call `typeset` and `draw` to see those.
+    pub fn render(&self, sources: &[Source]) -> Result<Vec<u8>> {
+        self.draw(&self.typeset(sources)?)
+    }
+}
+
+/// Sets the Markdown document `markdown` in `layout`, adding the images it
+/// shows (looked for under `base_dir`) to `images` and anything that
+/// couldn't be set to `warnings`. `document` says which of the PDF's
+/// documents this is, to keep footnotes apart.
+fn read_markdown(
+    layout: &mut Layout,
+    images: &mut Vec<DynamicImage>,
+    warnings: &mut Vec<Warning>,
+    markdown: &str,
+    base_dir: &Path,
+    highlighter: Option<&Highlighter>,
+    document: usize,
+) {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_SMART_PUNCTUATION);
+    let parser = MarkdownParser::new_ext(markdown, options);
+
+    let link_color = layout.theme.link_color.0;
+    let fonts = layout.fonts.clone();
+    let mut runs: Vec<Run> = Vec::new();
+    let mut style = Style::default();
+    // For each list being read, the number of its next item, or `None` if
+    // it is a bulleted list.
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    // The language of the fenced code block being read, and its text so far.
+    let mut code_block: Option<(String, String)> = None;
+    let mut table: Option<Table> = None;
+    let mut in_table_head = false;
+    // While reading an image's alt text: its URL, and the text before it.
+    let mut image: Option<(String, Vec<Run>)> = None;
+    // Footnote labels in the order they are first referred to, which gives
+    // their numbers, and their text in the order they are defined.
+    let mut footnote_labels: Vec<String> = Vec::new();
+    let mut footnotes: Vec<(String, Vec<Run>)> = Vec::new();
+    // While reading a footnote definition: its label, and the text before it.
+    let mut footnote: Option<(String, Vec<Run>)> = None;
+
+    for event in parser {
+        match event {
+            Event::Start(tag) => {
+                match tag {
+                    Tag::Heading(..) => flush(layout, &mut runs),
+                    Tag::CodeBlock(kind) => {
+                        flush(layout, &mut runs);
+                        let language = match kind {
+                            CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or("").to_string(),
+                            CodeBlockKind::Indented => String::new(),
+                        };
+                        code_block = Some((language, String::new()));
+                    }
+                    Tag::Table(alignments) => {
+                        flush(layout, &mut runs);
+                        let alignments = alignments
+                            .iter()
+                            .map(|alignment| match alignment {
+                                Alignment::Center => Align::Center,
+                                Alignment::Right => Align::Right,
+                                Alignment::Left | Alignment::None => Align::Left,
+                            })
+                            .collect();
+                        table = Some(Table { alignments, ..Table::default() });
+                    }
+                    Tag::TableHead => in_table_head = true,
+                    Tag::TableRow => {
+                        if let Some(table) = table.as_mut() {
+                            table.rows.push(Vec::new());
+                        }
+                    }
+                    Tag::Image(_, url, _) => {
+                        image = Some((url.to_string(), std::mem::take(&mut runs)));
+                    }
+                    Tag::Link(_, destination, _) => {
+                        style.link = Some(layout.link(&destination));
+                        style.color = Some(link_color);
+                    }
+                    Tag::FootnoteDefinition(label) => {
+                        footnote = Some((label.to_string(), std::mem::take(&mut runs)));
+                    }
+                    Tag::Strong => style.bold = true,
+                    Tag::Emphasis => style.italic = true,
+                    Tag::Strikethrough => style.strike = true,
+                    Tag::List(start) => {
+                        // The text of the item this list is nested in.
+                        flush(layout, &mut runs);
+                        lists.push(start);
+                        layout.start_list();
+                    }
+                    Tag::Item => {
+                        let marker = match lists.last_mut() {
+                            Some(Some(number)) => {
+                                *number += 1;
+                                format!("{}.", *number - 1)
+                            }
+                            _ => bullet(&fonts, lists.len()),
+                        };
+                        layout.list_marker(&marker);
+                    }
+                    Tag::BlockQuote => {
+                        flush(layout, &mut runs);
+                        layout.start_quote();
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(tag) => {
+                match tag {
+                    Tag::Heading(level, _, _) => {
+                        layout.heading(&runs, level as usize);
+                        runs.clear();
+                    }
+                    Tag::Paragraph if footnote.is_some() => {
+                        push_text(&mut runs, " ", style);
+                    }
+                    Tag::Paragraph | Tag::Item => flush(layout, &mut runs),
+                    Tag::CodeBlock(_) => {
+                        let (language, code) = code_block.take().unwrap_or_default();
+                        let lines = highlighter
+                            .filter(|_| !language.is_empty())
+                            .and_then(|highlighter| highlighter.lines(&code, &language))
+                            .unwrap_or_else(|| highlight::plain_lines(&code));
+                        layout.code_block(&lines);
+                    }
+                    Tag::TableCell => {
+                        let cell = std::mem::take(&mut runs);
+                        if let Some(table) = table.as_mut() {
+                            if in_table_head {
+                                table.header.push(cell);
+                            } else if let Some(row) = table.rows.last_mut() {
+                                row.push(cell);
+                            }
+                        }
+                    }
+                    Tag::TableHead => in_table_head = false,
+                    Tag::Table(_) => {
+                        if let Some(table) = table.take() {
+                            layout.table(&table);
+                        }
+                    }
+                    Tag::Image(..) => {
+                        if let Some((url, before)) = image.take() {
+                            let caption = std::mem::replace(&mut runs, before);
+                            match local_image(base_dir, &url) {
+                                Ok(loaded) => {
+                                    flush(layout, &mut runs);
+                                    layout.image(images.len(), (loaded.width(), loaded.height()), &caption);
+                                    images.push(loaded);
+                                }
+                                Err(error) => {
+                                    // Leave the alt text where the image would have been.
+                                    warnings.push(Warning::Image(format!("{:#}", error)));
+                                    runs.extend(caption);
+                                }
+                            }
+                        }
+                    }
+                    Tag::Link(..) => {
+                        style.link = None;
+                        style.color = None;
+                    }
+                    Tag::FootnoteDefinition(_) => {
+                        if let Some((label, before)) = footnote.take() {
+                            let text = std::mem::replace(&mut runs, before);
+                            footnotes.push((label, text));
+                        }
+                    }
+                    Tag::Strong => style.bold = false,
+                    Tag::Emphasis => style.italic = false,
+                    Tag::Strikethrough => style.strike = false,
+                    Tag::List(_) => {
+                        flush(layout, &mut runs);
+                        lists.pop();
+                        layout.end_list();
+                    }
+                    Tag::BlockQuote => {
+                        flush(layout, &mut runs);
+                        layout.end_quote();
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(text) if code_block.is_some() => {
+                code_block.as_mut().unwrap().1.push_str(&text);
+            }
+            Event::Text(text) => {
+                push_text(&mut runs, &text, style);
+            }
+            Event::Code(code) => {
+                push_text(&mut runs, &code, Style { code: true, ..style });
+            }
+            Event::FootnoteReference(label) => {
+                let number = match footnote_labels.iter().position(|known| *known == *label) {
+                    Some(index) => index + 1,
+                    None => {
+                        footnote_labels.push(label.to_string());
+                        footnote_labels.len()
+                    }
+                };
+                let link = layout.link(&format!("#fn:{}:{}", document, label));
+                let reference = Style { link: Some(link), color: Some(link_color), ..style };
+                push_text(&mut runs, &format!("[{}]", number), reference);
+            }
+            Event::SoftBreak => {
+                push_text(&mut runs, " ", style);
+            }
+            Event::HardBreak => flush(layout, &mut runs),
+            Event::Rule => {
+                flush(layout, &mut runs);
+                layout.horizontal_rule(1.0);
+            }
+            Event::TaskListMarker(checked) => {
+                // In place of the item's bullet.
+                let (marker, plain) = if checked { ('☒', "[x]") } else { ('☐', "[ ]") };
+                if fonts.has(&style, marker) {
+                    layout.list_marker(&marker.to_string());
+                } else {
+                    layout.list_marker(plain);
+                }
+            }
+            _ => {}
+        }
+    }
+    flush(layout, &mut runs);
+    warnings.extend(add_footnotes(layout, &footnote_labels, footnotes, document));
+}
+
+/// Sets the footnotes after a short rule at the end of the document:
+/// referenced ones numbered in the order they were first referred to, then
+/// any that nothing refers to. Returns the labels referred to but never
+/// defined, as warnings.
+fn add_footnotes(
+    layout: &mut Layout,
+    labels: &[String],
+    mut footnotes: Vec<(String, Vec<Run>)>,
+    document: usize,
+) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut numbered = Vec::new();
+    for label in labels {
+        match footnotes.iter().position(|(defined, _)| defined == label) {
+            Some(index) => numbered.push(footnotes.remove(index)),
+            None => warnings.push(Warning::UndefinedFootnote(label.clone())),
+        }
+    }
+    numbered.append(&mut footnotes);
+    if numbered.is_empty() {
+        return warnings;
+    }
+    layout.horizontal_rule(1.0 / 3.0);
+    for (index, (label, text)) in numbered.into_iter().enumerate() {
+        let number = labels.iter().position(|known| *known == label).map_or(index + 1, |position| position + 1);
+        layout.anchor(&format!("fn:{}:{}", document, label));
+        let mut runs = vec![Run::plain(&format!("{}. ", number))];
+        runs.extend(text);
+        layout.paragraph(&runs);
+    }
+    warnings
+}
+
+/// Sets the text read so far, if there is any, as a paragraph.
+fn flush(layout: &mut Layout, runs: &mut Vec<Run>) {
+    if !runs.is_empty() {
+        layout.paragraph(runs);
+        runs.clear();
+    }
+}
+
+/// The bullet for an item of a list nested `depth` lists deep, falling back
+/// to a plain bullet if no font has the one for that depth.
+fn bullet(fonts: &Fonts, depth: usize) -> String {
+    const BULLETS: [char; 3] = ['•', '◦', '▪'];
+    let bullet = BULLETS[depth.saturating_sub(1) % BULLETS.len()];
+    if fonts.has(&Style::default(), bullet) { bullet } else { '•' }.to_string()
+}
+
+/// The characters none of the fonts has, which come out blank.
+fn missing_glyphs(layout: &Layout) -> Option<Warning> {
+    let spans = layout.pages.iter().flat_map(|page| &page.lines).flat_map(|line| &line.spans);
+    let missing: BTreeSet<char> = spans
+        .flat_map(|span| span.text.chars().filter(|&c| !c.is_whitespace() && !layout.fonts.covers(span.face, c)))
+        .collect();
+    (!missing.is_empty()).then(|| Warning::MissingGlyphs(missing.into_iter().collect()))
+}
+
+/// Loads the image at `url`, taken relative to the Markdown file's directory.
+fn local_image(base_dir: &Path, url: &str) -> Result<DynamicImage> {
+    if url.contains("://") {
+        bail!("Skipping image {}: only local files can be embedded", url);
+    }
+    let path = base_dir.join(url);
+    if !path.is_file() {
+        bail!("Skipping image {}: no such file", path.display());
+    }
+    pdf::load_image(&path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::Block;
+
+    fn options() -> RenderOptions {
+        RenderOptions { date: Some("today".to_string()), ..RenderOptions::default() }
+    }
+
+    /// The text of each line of block number `block`, with its page.
+    fn lines(layout: &Layout, block: usize) -> Vec<(usize, String)> {
+        layout
+            .block_lines(block)
+            .map(|(page, line)| (page, line.spans.iter().map(|span| span.text.as_str()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn returns_warnings_instead_of_printing_them() {
+        let renderer = Renderer::new(options()).unwrap();
+        let markdown = "See[^gone].\n\n![logo](no-such-image.png)\n\n\u{E000}\n";
+        let typeset = renderer.typeset(&[Source::new(markdown)]).unwrap();
+        assert_eq!(typeset.warnings.len(), 3, "{:?}", typeset.warnings);
+        assert!(matches!(&typeset.warnings[0], Warning::Image(reason) if reason.contains("no-such-image.png")));
+        assert_eq!(typeset.warnings[1], Warning::UndefinedFootnote("gone".to_string()));
+        assert_eq!(typeset.warnings[2], Warning::MissingGlyphs("\u{E000}".to_string()));
+    }
+
+    #[test]
+    fn sets_each_markdown_block_as_a_block_of_lines() {
+        let markdown = "# Title\n\nSome *text*.\n\n- one\n- two\n\n```\ncode\n```\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n---\n\n> quoted\n";
+        let layout = layout(markdown, &options()).unwrap();
+        assert_eq!(
+            layout.blocks,
+            vec![
+                Block::Heading(1),
+                Block::Paragraph,
+                Block::ListItem,
+                Block::ListItem,
+                Block::CodeBlock,
+                Block::Table,
+                Block::Rule,
+                Block::Paragraph,
+            ]
+        );
+        assert_eq!(lines(&layout, 1), vec![(0, "Some text.".to_string())]);
+        assert_eq!(lines(&layout, 2), vec![(0, "•".to_string()), (0, "one".to_string())]);
+        assert_eq!(lines(&layout, 5), vec![(0, "a".to_string()), (0, "b".to_string()), (0, "1".to_string()), (0, "2".to_string())]);
+        assert_eq!(lines(&layout, 6), vec![]);
+        let paragraph = layout.block_lines(1).next().unwrap().1;
+        let quoted = layout.block_lines(7).next().unwrap().1;
+        assert!(quoted.x > paragraph.x);
+        let styles: Vec<Style> = paragraph.spans.iter().map(|span| span.style).collect();
+        assert_eq!(styles, vec![Style::default(), Style { italic: true, ..Style::default() }, Style::default()]);
+    }
+
+    #[test]
+    fn numbers_ordered_lists_from_their_start_and_hangs_the_numbers() {
+        let layout = layout("3. three\n4. four\n   1. nested\n", &options()).unwrap();
+        let item = |block| lines(&layout, block).into_iter().map(|(_, text)| text).collect::<Vec<_>>();
+        assert_eq!(item(0), vec!["3.", "three"]);
+        assert_eq!(item(1), vec!["4.", "four"]);
+        assert_eq!(item(2), vec!["1.", "nested"]);
+        let x = |block| layout.block_lines(block).map(|(_, line)| line.x).collect::<Vec<_>>();
+        assert!(x(0)[0] < x(0)[1]);
+        assert!(x(2)[1] > x(1)[1]);
+    }
+
+    #[test]
+    fn starts_each_source_on_a_new_page_with_its_own_footnotes() {
+        let renderer = Renderer::new(options()).unwrap();
+        let sources = [
+            Source { name: Some("sales".to_string()), ..Source::new("Up[^a].\n\n[^a]: A lot.\n") },
+            Source::new("# Ops\n\nFine[^a].\n\n[^a]: Really.\n"),
+        ];
+        let Typeset { layout, title, .. } = renderer.typeset(&sources).unwrap();
+        assert_eq!(title, "sales");
+        assert_eq!(layout.pages.len(), 2);
+        let heading = layout.blocks.iter().position(|block| *block == Block::Heading(1)).unwrap();
+        assert_eq!(lines(&layout, heading), vec![(1, "Ops".to_string())]);
+        assert_eq!(layout.anchors["fn:0:a"].page, 0);
+        assert_eq!(layout.anchors["fn:1:a"].page, 1);
+        assert!(layout.links.contains(&"#fn:1:a".to_string()));
+    }
+
+    #[test]
+    fn puts_the_contents_first_and_running_heads_outside_any_block() {
+        let mut options = RenderOptions { toc: true, title: Some("Report".to_string()), ..options() };
+        options.theme.header.left = "{title}".to_string();
+        let layout = layout("# One\n\ntext\n\n## Two\n", &options).unwrap();
+        assert_eq!(&layout.blocks[..3], &[Block::Heading(1), Block::ContentsEntry, Block::ContentsEntry]);
+        assert_eq!(lines(&layout, 1), vec![(0, "One".to_string()), (0, "2".to_string())]);
+        assert_eq!(lines(&layout, 3), vec![(1, "One".to_string())]);
+        let heads: Vec<(usize, String)> = layout
+            .pages
+            .iter()
+            .enumerate()
+            .flat_map(|(index, page)| page.lines.iter().filter(|line| line.block.is_none()).map(move |line| (index, line)))
+            .map(|(index, line)| (index, line.spans.iter().map(|span| span.text.as_str()).collect()))
+            .collect();
+        let expected = ["Report", "1 / 2", "Report", "2 / 2"];
+        assert_eq!(heads, expected.iter().enumerate().map(|(index, text)| (index / 2, text.to_string())).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn renders_a_pdf() {
+        let pdf = render("# Hello\n\nWorld.", &options()).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+        let margins = crate::theme::Margins { top: 500.0, right: 400.0, bottom: 500.0, left: 400.0 };
+        let theme = Theme { margins, ..Theme::default() };
+        assert!(render("text", &RenderOptions { theme, ..options() }).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! Cuts a TrueType font down to the glyphs a document uses before it is
+//! embedded, so a font with thousands of glyphs doesn't add megabytes to a
+//! two-page PDF.
+//!
+//! Unused glyphs are emptied rather than removed, so glyph ids stay as they
+//! were and the metrics still line up without rewriting them. The cmap is
+//! rewritten to list only the characters used, since printpdf gives every
+//! character in it a width and a ToUnicode entry, and tables only text
+//! shaping uses are dropped. Fonts with CFF outlines (most `.otf` files) and
+//! font collections are left for the caller to embed whole.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The tables a PDF viewer, or printpdf, needs from an embedded TrueType font.
+const KEPT_TABLES: [&[u8; 4]; 14] =
+    [b"OS/2", b"cmap", b"cvt ", b"fpgm", b"gasp", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"name", b"post", b"prep"];
+
+struct Table {
+    tag: [u8; 4],
+    data: Vec<u8>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn tables(font: &[u8]) -> Option<Vec<Table>> {
+    let count = u16_at(font, 4)? as usize;
+    (0..count)
+        .map(|index| {
+            let record = 12 + index * 16;
+            let tag = font.get(record..record + 4)?.try_into().ok()?;
+            let offset = u32_at(font, record + 8)? as usize;
+            let length = u32_at(font, record + 12)? as usize;
+            Some(Table { tag, data: font.get(offset..offset + length)?.to_vec() })
+        })
+        .collect()
+}
+
+fn table<'a>(tables: &'a [Table], tag: &[u8; 4]) -> Option<&'a [u8]> {
+    tables.iter().find(|table| &table.tag == tag).map(|table| table.data.as_slice())
+}
+
+/// The glyphs a composite glyph is built from; none for a simple glyph.
+fn components(glyph: &[u8]) -> Vec<u16> {
+    const WORDS: u16 = 0x0001;
+    const SCALE: u16 = 0x0008;
+    const MORE: u16 = 0x0020;
+    const XY_SCALE: u16 = 0x0040;
+    const TWO_BY_TWO: u16 = 0x0080;
+
+    let mut found = Vec::new();
+    if u16_at(glyph, 0).is_none_or(|contours| (contours as i16) >= 0) {
+        return found;
+    }
+    let mut offset = 10;
+    while let (Some(flags), Some(id)) = (u16_at(glyph, offset), u16_at(glyph, offset + 2)) {
+        found.push(id);
+        offset += 4 + if flags & WORDS != 0 { 4 } else { 2 };
+        offset += match flags {
+            _ if flags & SCALE != 0 => 2,
+            _ if flags & XY_SCALE != 0 => 4,
+            _ if flags & TWO_BY_TWO != 0 => 8,
+            _ => 0,
+        };
+        if flags & MORE == 0 {
+            break;
+        }
+    }
+    found
+}
+
+/// A cmap with one format 12 subtable, mapping each character in `chars`
+/// to its glyph.
+fn cmap(chars: &BTreeMap<char, u16>) -> Vec<u8> {
+    let mut table = Vec::new();
+    // Version 0 with one subtable: Windows (3), full Unicode (10), at offset 12.
+    for field in [0u16, 1, 3, 10] {
+        table.extend(field.to_be_bytes());
+    }
+    table.extend(12u32.to_be_bytes());
+    table.extend([0, 12, 0, 0]);
+    table.extend((16 + 12 * chars.len() as u32).to_be_bytes());
+    table.extend(0u32.to_be_bytes());
+    table.extend((chars.len() as u32).to_be_bytes());
+    for (&c, &glyph) in chars {
+        for field in [c as u32, c as u32, glyph as u32] {
+            table.extend(field.to_be_bytes());
+        }
+    }
+    table
+}
+
+/// `font` with only the outlines of the glyphs for `chars` (and of whatever
+/// they are built from) left in, or `None` if it isn't a TrueType font this
+/// can handle. `chars` maps each character to its glyph in `font`.
+pub fn subset(font: &[u8], chars: &BTreeMap<char, u16>) -> Option<Vec<u8>> {
+    let version = u32_at(font, 0)?;
+    if version != 0x0001_0000 && version != u32::from_be_bytes(*b"true") {
+        return None;
+    }
+    let mut tables = tables(font)?;
+    tables.retain(|table| KEPT_TABLES.contains(&&table.tag));
+    let long_offsets = u16_at(table(&tables, b"head")?, 50)? == 1;
+    let glyph_count = u16_at(table(&tables, b"maxp")?, 4)? as usize;
+    let loca = table(&tables, b"loca")?;
+    let glyf = table(&tables, b"glyf")?;
+    let offsets: Vec<usize> = (0..=glyph_count)
+        .map(|id| match long_offsets {
+            true => u32_at(loca, id * 4).map(|offset| offset as usize),
+            false => u16_at(loca, id * 2).map(|offset| offset as usize * 2),
+        })
+        .collect::<Option<_>>()?;
+    let glyph = |id: usize| glyf.get(offsets[id]..offsets[id + 1]);
+
+    // Glyph 0 is the "missing glyph" box, which every font must keep.
+    let mut keep: BTreeSet<u16> = chars.values().copied().filter(|&id| (id as usize) < glyph_count).collect();
+    keep.insert(0);
+    let mut pending: Vec<u16> = keep.iter().copied().collect();
+    while let Some(id) = pending.pop() {
+        for component in components(glyph(id as usize)?) {
+            if (component as usize) < glyph_count && keep.insert(component) {
+                pending.push(component);
+            }
+        }
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_offsets = vec![0];
+    for id in 0..glyph_count {
+        if keep.contains(&(id as u16)) {
+            new_glyf.extend_from_slice(glyph(id)?);
+            new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+        }
+        new_offsets.push(new_glyf.len());
+    }
+    let new_loca: Vec<u8> = match long_offsets {
+        true => new_offsets.iter().flat_map(|&offset| (offset as u32).to_be_bytes()).collect(),
+        false => new_offsets.iter().flat_map(|&offset| ((offset / 2) as u16).to_be_bytes()).collect(),
+    };
+    for table in &mut tables {
+        match &table.tag {
+            b"glyf" => table.data = std::mem::take(&mut new_glyf),
+            b"loca" => table.data = new_loca.clone(),
+            b"cmap" => table.data = cmap(chars),
+            // Version 3 has no glyph names, which would list every glyph.
+            b"post" if table.data.len() >= 32 => {
+                table.data.truncate(32);
+                table.data[0..4].copy_from_slice(&0x0003_0000u32.to_be_bytes());
+            },
+            _ => {},
+        }
+    }
+    Some(assemble(version, tables))
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4)
+        .map(|chunk| {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_be_bytes(word)
+        })
+        .fold(0, u32::wrapping_add)
+}
+
+/// Writes the tables out as a font file, with the table directory and the
+/// checksums recomputed.
+fn assemble(version: u32, mut tables: Vec<Table>) -> Vec<u8> {
+    tables.sort_by_key(|table| table.tag);
+    // head's checkSumAdjustment is worked out over the finished file, with
+    // itself counted as zero.
+    if let Some(head) = tables.iter_mut().find(|table| &table.tag == b"head") {
+        head.data[8..12].fill(0);
+    }
+    let count = tables.len() as u16;
+    let entry_selector = 15 - count.max(1).leading_zeros() as u16;
+    let search_range = (1 << entry_selector) * 16;
+
+    let mut file = Vec::new();
+    file.extend(version.to_be_bytes());
+    for field in [count, search_range, entry_selector, count * 16 - search_range] {
+        file.extend(field.to_be_bytes());
+    }
+    let mut offset = 12 + 16 * tables.len();
+    let mut head_offset = None;
+    for table in &tables {
+        if &table.tag == b"head" {
+            head_offset = Some(offset);
+        }
+        file.extend(table.tag);
+        file.extend(checksum(&table.data).to_be_bytes());
+        file.extend((offset as u32).to_be_bytes());
+        file.extend((table.data.len() as u32).to_be_bytes());
+        offset += table.data.len().next_multiple_of(4);
+    }
+    for table in &tables {
+        file.extend(&table.data);
+        file.resize(file.len().next_multiple_of(4), 0);
+    }
+    if let Some(head) = head_offset {
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&file));
+        file[head + 8..head + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A font with just enough tables to subset: glyph 1 a simple glyph,
+    /// glyph 2 a composite of glyph 1, glyph 3 another simple glyph.
+    fn font() -> Vec<u8> {
+        let simple = |fill: u8| {
+            let mut glyph = vec![0, 1];
+            glyph.extend([fill; 10]);
+            glyph
+        };
+        let composite = vec![0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, 0x02, 0x00, 0x01, 0, 0, 0, 0];
+        let glyphs = [vec![], simple(0x11), composite, simple(0x33)];
+        let mut glyf = Vec::new();
+        let mut loca = vec![0u32];
+        for glyph in &glyphs {
+            glyf.extend(glyph);
+            glyf.resize(glyf.len().next_multiple_of(4), 0);
+            loca.push(glyf.len() as u32);
+        }
+        let mut head = vec![0; 54];
+        head[51] = 1;
+        let mut maxp = vec![0; 6];
+        maxp[5] = glyphs.len() as u8;
+        let tables = vec![
+            Table { tag: *b"cmap", data: cmap(&BTreeMap::new()) },
+            Table { tag: *b"glyf", data: glyf },
+            Table { tag: *b"head", data: head },
+            Table { tag: *b"loca", data: loca.iter().flat_map(|offset| offset.to_be_bytes()).collect() },
+            Table { tag: *b"maxp", data: maxp },
+        ];
+        assemble(0x0001_0000, tables)
+    }
+
+    fn glyph_lengths(font: &[u8]) -> Vec<u32> {
+        let tables = tables(font).unwrap();
+        let loca = table(&tables, b"loca").unwrap();
+        let offsets: Vec<u32> = (0..5).map(|id| u32_at(loca, id * 4).unwrap()).collect();
+        offsets.windows(2).map(|pair| pair[1] - pair[0]).collect()
+    }
+
+    #[test]
+    fn keeps_used_glyphs_and_their_components() {
+        let font = font();
+        assert_eq!(glyph_lengths(&font), vec![0, 12, 20, 12]);
+        let subset = subset(&font, &BTreeMap::from([('x', 2)])).unwrap();
+        assert_eq!(glyph_lengths(&subset), vec![0, 12, 20, 0]);
+        assert_eq!(checksum(&subset), 0xB1B0_AFBA);
+        let subset_tables = tables(&subset).unwrap();
+        let cmap = owned_ttf_parser::cmap::Table::parse(table(&subset_tables, b"cmap").unwrap()).unwrap();
+        let subtable = cmap.subtables.get(0).unwrap();
+        assert_eq!(subtable.glyph_index('x' as u32).map(|glyph| glyph.0), Some(2));
+        assert_eq!(subtable.glyph_index('y' as u32), None);
+        assert!(super::subset(b"OTTO\0\0\0\0", &BTreeMap::new()).is_none());
+    }
+}
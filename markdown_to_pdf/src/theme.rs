@@ -0,0 +1,407 @@
+//! Page setup and the look of the document: page size, margins, type sizes,
+//! spacing, colours and the running header and footer. A theme is read from
+//! a TOML file, and any setting left out keeps its default:
+//!
+//! ```toml
+//! page_size = "letter"
+//! margins = "1in 0.75in"
+//! font_size = 11
+//! link_color = "#0b5394"
+//! fallback_fonts = ["/usr/share/fonts/noto/NotoSansSymbols2-Regular.ttf"]
+//!
+//! [font]
+//! regular = "fonts/SourceSerif4-Regular.ttf"
+//! bold = "fonts/SourceSerif4-Bold.ttf"
+//!
+//! [footer]
+//! left = "{title}"
+//! right = "{page} / {pages}"
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+/// A length in points, written in a theme or on the command line as a
+/// number of points or with a `pt`, `mm`, `cm` or `in` unit.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "LengthValue")]
+pub struct Length(pub f32);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LengthValue {
+    Points(f32),
+    Text(String),
+}
+
+impl TryFrom<LengthValue> for Length {
+    type Error = String;
+
+    fn try_from(value: LengthValue) -> Result<Self, Self::Error> {
+        match value {
+            LengthValue::Points(points) => Ok(Length(points)),
+            LengthValue::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl FromStr for Length {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let split = text.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let number: f32 = number.trim().parse().map_err(|_| format!("not a length: {:?}", text))?;
+        let points_per_unit = match unit {
+            "" | "pt" => 1.0,
+            "mm" => 72.0 / 25.4,
+            "cm" => 72.0 / 2.54,
+            "in" => 72.0,
+            _ => return Err(format!("unknown unit {:?} in {:?} (use pt, mm, cm or in)", unit, text)),
+        };
+        Ok(Length(number * points_per_unit))
+    }
+}
+
+/// The page's width and height in points: `a4`, `a5`, `letter`, `legal`,
+/// or a custom `WIDTHxHEIGHT` such as `6x9in` or `148mmx210mm`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PageSize {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl PageSize {
+    pub const A4: PageSize = PageSize { width: 595.28, height: 841.89 };
+    pub const A5: PageSize = PageSize { width: 419.53, height: 595.28 };
+    pub const LETTER: PageSize = PageSize { width: 612.0, height: 792.0 };
+    pub const LEGAL: PageSize = PageSize { width: 612.0, height: 1008.0 };
+}
+
+impl TryFrom<String> for PageSize {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl FromStr for PageSize {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_lowercase().as_str() {
+            "a4" => return Ok(PageSize::A4),
+            "a5" => return Ok(PageSize::A5),
+            "letter" => return Ok(PageSize::LETTER),
+            "legal" => return Ok(PageSize::LEGAL),
+            _ => {},
+        }
+        let (width, height) = text
+            .split_once(['x', 'X'])
+            .ok_or_else(|| format!("unknown page size {:?} (use a4, a5, letter, legal or WIDTHxHEIGHT)", text))?;
+        // "6x9in" gives the width the height's unit.
+        let unit: String = height.trim().chars().skip_while(|c| !c.is_ascii_alphabetic()).collect();
+        let width = if width.trim().ends_with(|c: char| c.is_ascii_alphabetic()) {
+            width.parse::<Length>()?
+        } else {
+            format!("{}{}", width.trim(), unit).parse::<Length>()?
+        };
+        let height: Length = height.parse()?;
+        if width.0 <= 0.0 || height.0 <= 0.0 {
+            return Err(format!("page size {:?} is empty", text));
+        }
+        Ok(PageSize { width: width.0, height: height.0 })
+    }
+}
+
+/// Margins in points, written like CSS: one length for every side, two for
+/// top and bottom then left and right, or four for top, right, bottom, left.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "LengthValue")]
+pub struct Margins {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl TryFrom<LengthValue> for Margins {
+    type Error = String;
+
+    fn try_from(value: LengthValue) -> Result<Self, Self::Error> {
+        match value {
+            LengthValue::Points(points) => Ok(Margins { top: points, right: points, bottom: points, left: points }),
+            LengthValue::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl FromStr for Margins {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let lengths = text.split_whitespace().map(|part| part.parse::<Length>().map(|length| length.0));
+        match lengths.collect::<Result<Vec<f32>, _>>()?[..] {
+            [all] => Ok(Margins { top: all, right: all, bottom: all, left: all }),
+            [vertical, horizontal] => Ok(Margins { top: vertical, right: horizontal, bottom: vertical, left: horizontal }),
+            [top, right, bottom, left] => Ok(Margins { top, right, bottom, left }),
+            _ => Err(format!("margins {:?} should be one, two or four lengths", text)),
+        }
+    }
+}
+
+/// An RGB colour, written `#rrggbb`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color(pub [u8; 3]);
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let hex = text.trim().trim_start_matches('#');
+        let channel = |index: usize| hex.get(index..index + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok());
+        match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Color([r, g, b])),
+            _ => Err(format!("not a colour: {:?} (use #rrggbb)", text)),
+        }
+    }
+}
+
+/// What goes at the left, centre and right of a running header or footer.
+/// `{title}`, `{date}`, `{page}` and `{pages}` are filled in on each page.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Slots {
+    pub left: String,
+    pub center: String,
+    pub right: String,
+}
+
+/// On the command line, `LEFT|CENTER|RIGHT`; a single part goes in the
+/// centre.
+impl FromStr for Slots {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<String> = text.split('|').map(|part| part.trim().to_string()).collect();
+        match &parts[..] {
+            [center] => Ok(Slots { center: center.clone(), ..Slots::default() }),
+            [left, center, right] => Ok(Slots { left: left.clone(), center: center.clone(), right: right.clone() }),
+            _ => Err(format!("{:?} should be TEXT or LEFT|CENTER|RIGHT", text)),
+        }
+    }
+}
+
+/// TrueType or OpenType files for one family of fonts. Without a regular
+/// face the builtin font is used; a missing bold or italic face falls back
+/// to the regular one, and a missing bold italic to the bold.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FontFiles {
+    pub regular: Option<PathBuf>,
+    pub bold: Option<PathBuf>,
+    pub italic: Option<PathBuf>,
+    pub bold_italic: Option<PathBuf>,
+}
+
+impl FontFiles {
+    fn paths_mut(&mut self) -> impl Iterator<Item = &mut PathBuf> {
+        [&mut self.regular, &mut self.bold, &mut self.italic, &mut self.bold_italic].into_iter().flatten()
+    }
+}
+
+/// On the command line, `REGULAR[,BOLD[,ITALIC[,BOLD_ITALIC]]]`, with
+/// empty parts left out.
+impl FromStr for FontFiles {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut paths = text.split(',').map(|part| Some(part.trim()).filter(|part| !part.is_empty()).map(PathBuf::from));
+        let files = FontFiles {
+            regular: paths.next().flatten(),
+            bold: paths.next().flatten(),
+            italic: paths.next().flatten(),
+            bold_italic: paths.next().flatten(),
+        };
+        match (files.regular.is_some(), paths.next()) {
+            (true, None) => Ok(files),
+            (false, _) => Err(format!("{:?} doesn't name a regular font", text)),
+            (_, Some(_)) => Err(format!("{:?} should be REGULAR[,BOLD[,ITALIC[,BOLD_ITALIC]]]", text)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub page_size: PageSize,
+    pub margins: Margins,
+    /// Size of body text, in points.
+    pub font_size: f32,
+    /// Sizes of headings of levels 1 to 6.
+    pub heading_sizes: [f32; 6],
+    pub code_size: f32,
+    /// Size of image captions, and of the header and footer.
+    pub small_size: f32,
+    /// Distance between baselines, as a multiple of the font size.
+    pub line_spacing: f32,
+    /// Space left after a paragraph, as a multiple of the line height.
+    pub paragraph_spacing: f32,
+    pub text_color: Color,
+    pub heading_color: Color,
+    pub link_color: Color,
+    pub code_background: Color,
+    /// The colour of the bar beside a blockquote.
+    pub quote_color: Color,
+    /// The colour of the header and footer.
+    pub running_color: Color,
+    /// How `{date}` is written, in chrono's `strftime` format.
+    pub date_format: String,
+    pub header: Slots,
+    pub footer: Slots,
+    /// Fonts for body text and headings.
+    pub font: FontFiles,
+    /// Fonts for code.
+    pub mono_font: FontFiles,
+    /// Fonts tried, in order, for characters the text's own font lacks.
+    pub fallback_fonts: Vec<PathBuf>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let margin = 72.0 * 20.0 / 25.4;
+        Theme {
+            page_size: PageSize::A4,
+            margins: Margins { top: margin, right: margin, bottom: margin, left: margin },
+            font_size: 12.0,
+            heading_sizes: [24.0, 20.0, 16.0, 14.0, 12.0, 11.0],
+            code_size: 10.0,
+            small_size: 10.0,
+            line_spacing: 1.4,
+            paragraph_spacing: 0.5,
+            text_color: Color([0, 0, 0]),
+            heading_color: Color([0, 0, 0]),
+            link_color: Color([0, 72, 170]),
+            code_background: Color([242, 242, 242]),
+            quote_color: Color([208, 215, 222]),
+            running_color: Color([110, 110, 110]),
+            date_format: "%Y-%m-%d".to_string(),
+            header: Slots::default(),
+            footer: Slots { center: "{page} / {pages}".to_string(), ..Slots::default() },
+            font: FontFiles::default(),
+            mono_font: FontFiles::default(),
+            fallback_fonts: Vec::new(),
+        }
+    }
+}
+
+impl Theme {
+    /// Reads a theme, taking the font paths in it relative to the theme
+    /// file's directory.
+    pub fn from_file(path: &Path) -> Result<Theme> {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read theme: {}", path.display()))?;
+        let mut theme: Theme = toml::from_str(&text).with_context(|| format!("Invalid theme: {}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let fonts = theme.font.paths_mut().chain(theme.mono_font.paths_mut()).chain(&mut theme.fallback_fonts);
+        for font in fonts {
+            *font = dir.join(&*font);
+        }
+        theme.validate().with_context(|| format!("Invalid theme: {}", path.display()))?;
+        Ok(theme)
+    }
+
+    /// Checks that the type sizes and line spacing are positive, as nothing
+    /// can be laid out otherwise.
+    pub fn validate(&self) -> Result<()> {
+        let sizes = [("font_size", self.font_size), ("code_size", self.code_size), ("small_size", self.small_size)];
+        let headings = self.heading_sizes.iter().map(|size| ("heading_sizes", *size));
+        for (name, value) in sizes.into_iter().chain(headings).chain([("line_spacing", self.line_spacing)]) {
+            if !(value.is_finite() && value > 0.0) {
+                bail!("{} must be a positive number, not {}", name, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the body text to `size` points, scaling the other type sizes
+    /// along with it.
+    pub fn set_font_size(&mut self, size: f32) {
+        let scale = size / self.font_size;
+        self.font_size = size;
+        for heading in &mut self.heading_sizes {
+            *heading *= scale;
+        }
+        self.code_size *= scale;
+        self.small_size *= scale;
+    }
+
+    /// Width between the left and right margins.
+    pub fn text_width(&self) -> f32 {
+        self.page_size.width - self.margins.left - self.margins.right
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes_lengths_and_margins() {
+        assert_eq!("Letter".parse::<PageSize>().unwrap(), PageSize::LETTER);
+        assert_eq!("6x9in".parse::<PageSize>().unwrap(), PageSize { width: 432.0, height: 648.0 });
+        assert_eq!("400x600".parse::<PageSize>().unwrap(), PageSize { width: 400.0, height: 600.0 });
+        assert!("b7".parse::<PageSize>().is_err());
+        assert_eq!("1in".parse::<Length>().unwrap(), Length(72.0));
+        assert!((("25.4mm".parse::<Length>().unwrap().0) - 72.0).abs() < 0.001);
+        assert!("3furlongs".parse::<Length>().is_err());
+        let margins: Margins = "1in 36".parse().unwrap();
+        assert_eq!(margins, Margins { top: 72.0, right: 36.0, bottom: 72.0, left: 36.0 });
+        assert!("1 2 3".parse::<Margins>().is_err());
+        assert_eq!("{title}".parse::<Slots>().unwrap().center, "{title}");
+        assert_eq!("a||b".parse::<Slots>().unwrap(), Slots { left: "a".into(), center: "".into(), right: "b".into() });
+        let fonts: FontFiles = "Serif.ttf,,Serif-Italic.ttf".parse().unwrap();
+        assert_eq!(fonts.regular, Some(PathBuf::from("Serif.ttf")));
+        assert_eq!((fonts.bold, fonts.italic), (None, Some(PathBuf::from("Serif-Italic.ttf"))));
+        assert!(",Bold.ttf".parse::<FontFiles>().is_err());
+        assert!("a,b,c,d,e".parse::<FontFiles>().is_err());
+    }
+
+    #[test]
+    fn reads_a_partial_theme_over_the_defaults() {
+        let theme: Theme = toml::from_str(
+            "page_size = \"letter\"\nmargins = 36\nfont_size = 11\nlink_color = \"#0b5394\"\n[header]\nright = \"{date}\"\n",
+        )
+        .unwrap();
+        assert_eq!(theme.page_size, PageSize::LETTER);
+        assert_eq!(theme.margins.left, 36.0);
+        assert_eq!(theme.font_size, 11.0);
+        assert_eq!(theme.link_color, Color([0x0b, 0x53, 0x94]));
+        assert_eq!(theme.header.right, "{date}");
+        assert_eq!(theme.footer, Theme::default().footer);
+        assert!(toml::from_str::<Theme>("font_sise = 11").is_err());
+        let mut theme = Theme::default();
+        theme.set_font_size(9.0);
+        assert_eq!((theme.heading_sizes[0], theme.code_size), (18.0, 7.5));
+        assert!(toml::from_str::<Theme>("text_color = \"red\"").is_err());
+    }
+
+    #[test]
+    fn sizes_and_spacing_must_be_positive() {
+        assert!(Theme::default().validate().is_ok());
+        let mut theme = Theme::default();
+        theme.set_font_size(0.0);
+        assert_eq!(theme.validate().unwrap_err().to_string(), "font_size must be a positive number, not 0");
+        let theme: Theme = toml::from_str("heading_sizes = [24, 20, 16, -1, 12, 11]").unwrap();
+        assert!(theme.validate().is_err());
+        let theme: Theme = toml::from_str("line_spacing = 0").unwrap();
+        assert!(theme.validate().is_err());
+    }
+}
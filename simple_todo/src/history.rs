@@ -0,0 +1,278 @@
+//! Atomic writes and the append-only operation log kept next to each todo
+//! file (`<file>.log`).
+//!
+//! Every write records the file's contents before and after as one JSON line,
+//! so `undo` can put any earlier state back. An undo is itself logged with
+//! how many operations it reverted, which keeps the log append-only, except
+//! that only the newest `MAX_OPERATIONS` are kept.
+//!
+//! A move changes two lists, each with its own log, so it can't be undone
+//! from either one; moving the task back undoes it.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const UNDO: &str = "undo";
+/// What `Lists::move_task` logs its two writes as, followed by the other list.
+pub const MOVE_TO: &str = "move to";
+pub const MOVE_IN_FROM: &str = "move in from";
+/// How many operations the log keeps, and so how far back `undo` can go.
+const MAX_OPERATIONS: usize = 200;
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Operation {
+    time: String,
+    op: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    undoes: Option<usize>,
+    before: String,
+    after: String,
+}
+
+fn log_path(filename: &str) -> PathBuf {
+    PathBuf::from(format!("{}.log", filename))
+}
+
+/// Writes to a temporary file beside `path` and renames it into place, so a
+/// crash leaves either the old contents or the new ones, never half of each.
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name));
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+/// Appends `operation` to the log, then drops the oldest operations if there
+/// are more than `MAX_OPERATIONS`. Playing the log back still works after
+/// that, since an undo only ever reverts the operations just before it.
+fn append(filename: &str, operation: &Operation) -> io::Result<()> {
+    let line = serde_json::to_string(operation)?;
+    let path = log_path(filename);
+    let mut log = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(log, "{}", line)?;
+    log.sync_all()?;
+    let contents = fs::read_to_string(&path)?;
+    let lines: Vec<&str> = contents.lines().collect();
+    if lines.len() > MAX_OPERATIONS {
+        let kept = lines[lines.len() - MAX_OPERATIONS..].join("\n");
+        write_atomically(&path, &format!("{}\n", kept))?;
+    }
+    Ok(())
+}
+
+fn read_log(filename: &str) -> Vec<Operation> {
+    let Ok(file) = File::open(log_path(filename)) else {
+        return Vec::new();
+    };
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(&line) {
+            Ok(operation) => Some(operation),
+            Err(error) => {
+                println!("skipping unreadable log line: {}", error);
+                None
+            },
+        })
+        .collect()
+}
+
+/// Writes `after` to `filename` and logs it as `op`, unless nothing changed.
+pub fn write_and_record(filename: &str, op: &str, after: String) -> io::Result<()> {
+    let before = fs::read_to_string(filename).unwrap_or_default();
+    if before == after {
+        return Ok(());
+    }
+    write_atomically(Path::new(filename), &after)?;
+    append(filename, &Operation {
+        time: Local::now().format(TIME_FORMAT).to_string(),
+        op: op.to_string(),
+        undoes: None,
+        before,
+        after,
+    })
+}
+
+/// Moves the log along with its list when the list is renamed or archived.
+pub fn move_log(from: &Path, to: &Path) -> io::Result<()> {
+    let from_log = log_path(&from.to_string_lossy());
+    if from_log.exists() {
+        fs::rename(from_log, log_path(&to.to_string_lossy()))?;
+    }
+    Ok(())
+}
+
+/// Indexes of the logged operations that are still in effect, oldest first,
+/// after playing back every undo.
+fn applied(log: &[Operation]) -> Vec<usize> {
+    let mut stack: Vec<usize> = Vec::new();
+    for (index, operation) in log.iter().enumerate() {
+        match operation.undoes {
+            Some(count) if operation.op == UNDO => {
+                stack.truncate(stack.len().saturating_sub(count));
+            },
+            _ => stack.push(index),
+        }
+    }
+    stack
+}
+
+fn row_changes(before: &str, after: &str) -> (Vec<String>, Vec<String>) {
+    let before_rows: HashSet<&str> = before.lines().filter(|row| !row.is_empty()).collect();
+    let after_rows: HashSet<&str> = after.lines().filter(|row| !row.is_empty()).collect();
+    let removed = before.lines().filter(|row| !row.is_empty() && !after_rows.contains(row));
+    let added = after.lines().filter(|row| !row.is_empty() && !before_rows.contains(row));
+    (removed.map(String::from).collect(), added.map(String::from).collect())
+}
+
+/// Reverts the last `count` operations. Refuses if the file was changed
+/// outside simple_todo since the last one, as those edits would be lost,
+/// unless `force` is set.
+pub fn undo(filename: &str, count: usize, force: bool) -> Result<(), String> {
+    let log = read_log(filename);
+    let stack = applied(&log);
+    if count == 0 || stack.is_empty() {
+        return Err("Nothing to undo".to_string());
+    }
+    let count = count.min(stack.len());
+    let reverting = &stack[stack.len() - count..];
+    if let Some(&index) = reverting.iter().find(|&&index| is_move(&log[index].op)) {
+        return Err(format!(
+            "Can't undo '{}' from {} as it also changed the other list; move the task back instead",
+            log[index].op, log[index].time
+        ));
+    }
+    let current = fs::read_to_string(filename).unwrap_or_default();
+    if current != log[*stack.last().unwrap()].after && !force {
+        return Err(format!(
+            "{} changed outside simple_todo since the last logged operation; undo would lose those edits \
+             (use --force to undo anyway)",
+            filename
+        ));
+    }
+    let restored = log[reverting[0]].before.clone();
+    write_atomically(Path::new(filename), &restored)
+        .map_err(|error| format!("Error writing {}: {}", filename, error))?;
+    append(filename, &Operation {
+        time: Local::now().format(TIME_FORMAT).to_string(),
+        op: UNDO.to_string(),
+        undoes: Some(count),
+        before: current,
+        after: restored,
+    })
+    .map_err(|error| format!("Error writing {}: {}", log_path(filename).display(), error))?;
+    for &index in reverting.iter().rev() {
+        println!("Undid {} from {}", log[index].op, log[index].time);
+    }
+    Ok(())
+}
+
+fn is_move(op: &str) -> bool {
+    op.starts_with(MOVE_TO) || op.starts_with(MOVE_IN_FROM)
+}
+
+pub fn show_log(filename: &str, limit: usize) {
+    let log = read_log(filename);
+    if log.is_empty() {
+        println!("No changes logged for {}", filename);
+        return;
+    }
+    for operation in log.iter().rev().take(limit) {
+        match operation.undoes {
+            Some(count) => println!("{}  {} ({} operation(s))", operation.time, operation.op, count),
+            None => println!("{}  {}", operation.time, operation.op),
+        }
+        let (removed, added) = row_changes(&operation.before, &operation.after);
+        removed.iter().for_each(|row| println!("  - {}", row));
+        added.iter().for_each(|row| println!("  + {}", row));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operation(op: &str, undoes: Option<usize>) -> Operation {
+        Operation {
+            time: String::new(),
+            op: op.to_string(),
+            undoes,
+            before: String::new(),
+            after: String::new(),
+        }
+    }
+
+    #[test]
+    fn undo_records_pop_operations() {
+        let log = vec![
+            operation("add", None),
+            operation("add", None),
+            operation("clean-up", None),
+            operation(UNDO, Some(2)),
+            operation("tui", None),
+        ];
+        assert_eq!(applied(&log), vec![0, 4]);
+        let log = [log, vec![operation(UNDO, Some(5))]].concat();
+        assert!(applied(&log).is_empty());
+    }
+
+    fn log_file(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("simple_todo-history-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let filename = dir.join("todo").to_string_lossy().to_string();
+        fs::remove_file(&filename).ok();
+        fs::remove_file(log_path(&filename)).ok();
+        filename
+    }
+
+    #[test]
+    fn keeps_only_the_newest_operations() {
+        let filename = log_file("cap");
+        for index in 0..MAX_OPERATIONS + 5 {
+            write_and_record(&filename, "add", index.to_string()).unwrap();
+        }
+        let log = read_log(&filename);
+        assert_eq!(log.len(), MAX_OPERATIONS);
+        assert_eq!(log[0].after, "5");
+        undo(&filename, 2, false).unwrap();
+        assert_eq!(fs::read_to_string(&filename).unwrap(), (MAX_OPERATIONS + 2).to_string());
+    }
+
+    #[test]
+    fn refuses_to_undo_a_move() {
+        let filename = log_file("move");
+        write_and_record(&filename, "add", "a\nb".to_string()).unwrap();
+        write_and_record(&filename, &format!("{} work", MOVE_TO), "a".to_string()).unwrap();
+        assert!(undo(&filename, 1, true).unwrap_err().contains("move the task back"));
+        assert!(undo(&filename, 2, true).is_err());
+        assert_eq!(fs::read_to_string(&filename).unwrap(), "a");
+    }
+
+    #[test]
+    fn refuses_to_lose_edits_made_elsewhere() {
+        let filename = log_file("outside");
+        write_and_record(&filename, "add", "a".to_string()).unwrap();
+        write_and_record(&filename, "add", "a\nb".to_string()).unwrap();
+        fs::write(&filename, "a\nb\nc").unwrap();
+        assert!(undo(&filename, 1, false).unwrap_err().contains("--force"));
+        assert_eq!(fs::read_to_string(&filename).unwrap(), "a\nb\nc");
+        undo(&filename, 1, true).unwrap();
+        assert_eq!(fs::read_to_string(&filename).unwrap(), "a");
+    }
+
+    #[test]
+    fn finds_changed_rows() {
+        let (removed, added) = row_changes("a|>one|>1\nb|>two|>2", "b|>two|>2\nc|>three|>3");
+        assert_eq!(removed, vec!["a|>one|>1".to_string()]);
+        assert_eq!(added, vec!["c|>three|>3".to_string()]);
+    }
+}
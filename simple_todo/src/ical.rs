@@ -0,0 +1,269 @@
+//! Conversion between `TodoEntry` rows and iCalendar `VTODO` components
+//! (RFC 5545).
+//!
+//! Times are written as floating local times, except `DTSTAMP` and
+//! `COMPLETED` which the RFC requires in UTC. On import, UTC times are moved
+//! to local time and `TZID` parameters are ignored (the time is taken as local).
+
+use chrono::prelude::*;
+
+use crate::{TodoEntry, DATE_FORMAT};
+
+const ICAL_DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%S";
+const ICAL_DATE_FORMAT: &str = "%Y%m%d";
+const MAX_LINE_OCTETS: usize = 75;
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            // rows in the todo file are one line each
+            Some('n') | Some('N') => unescaped.push(' '),
+            Some(other) => unescaped.push(other),
+            None => {},
+        }
+    }
+    unescaped
+}
+
+/// Splits on commas that aren't escaped, for list values like `CATEGORIES`.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in value.chars() {
+        if escaped {
+            current.push('\\');
+            current.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == ',' {
+            items.push(unescape_text(&current));
+            current.clear();
+        } else {
+            current.push(c);
+        }
+    }
+    items.push(unescape_text(&current));
+    items.into_iter().filter(|item| !item.is_empty()).collect()
+}
+
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn unfold_lines(contents: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        match (line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')), lines.last_mut()) {
+            (Some(continuation), Some(previous)) => previous.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn stamp_to_ical(stamp: &str) -> Option<String> {
+    NaiveDateTime::parse_from_str(stamp, DATE_FORMAT)
+        .ok()
+        .map(|time| time.format(ICAL_DATE_TIME_FORMAT).to_string())
+}
+
+fn stamp_to_utc(stamp: &str) -> Option<String> {
+    let local = NaiveDateTime::parse_from_str(stamp, DATE_FORMAT).ok()?;
+    let local = Local.from_local_datetime(&local).earliest()?;
+    Some(format!("{}Z", local.with_timezone(&Utc).format(ICAL_DATE_TIME_FORMAT)))
+}
+
+fn ical_to_stamp(value: &str) -> Option<String> {
+    let time = if let Some(utc) = value.strip_suffix('Z') {
+        let utc = NaiveDateTime::parse_from_str(utc, ICAL_DATE_TIME_FORMAT).ok()?;
+        Utc.from_utc_datetime(&utc).with_timezone(&Local).naive_local()
+    } else if !value.contains('T') {
+        NaiveDate::parse_from_str(value, ICAL_DATE_FORMAT).ok()?.and_hms_opt(0, 0, 0)?
+    } else {
+        NaiveDateTime::parse_from_str(value, ICAL_DATE_TIME_FORMAT).ok()?
+    };
+    Some(time.format(DATE_FORMAT).to_string())
+}
+
+fn priority_to_ical(priority: char) -> u32 {
+    (priority as u32).saturating_sub('A' as u32).min(8) + 1
+}
+
+fn priority_from_ical(value: &str) -> Option<char> {
+    match value.trim().parse::<u32>() {
+        Ok(level @ 1..=9) => char::from_u32('A' as u32 + level - 1),
+        _ => None,
+    }
+}
+
+/// 64-bit FNV-1a, which unlike std's hasher gives the same value on every
+/// Rust release, so a task keeps its UID from one export to the next.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn uid_for(entry: &TodoEntry) -> String {
+    format!("{}-{:016x}@simple_todo", entry.create_date, fnv1a(&entry.task_description))
+}
+
+pub fn render(entries: &[TodoEntry]) -> String {
+    let now = Utc::now().format(ICAL_DATE_TIME_FORMAT).to_string();
+    let mut lines: Vec<String> = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//rusty-tools//simple_todo//EN".to_string(),
+    ];
+    for entry in entries {
+        lines.push("BEGIN:VTODO".to_string());
+        lines.push(format!("UID:{}", uid_for(entry)));
+        lines.push(format!("DTSTAMP:{}Z", now));
+        if let Some(created) = stamp_to_ical(&entry.create_date) {
+            lines.push(format!("CREATED:{}", created));
+        }
+        lines.push(format!("SUMMARY:{}", escape_text(&entry.task_description)));
+        if let Some(due) = stamp_to_ical(&entry.due_date) {
+            lines.push(format!("DUE:{}", due));
+        }
+        if let Some(priority) = entry.priority {
+            lines.push(format!("PRIORITY:{}", priority_to_ical(priority)));
+        }
+        match &entry.completed {
+            Some(completed) => {
+                lines.push("STATUS:COMPLETED".to_string());
+                lines.extend(stamp_to_utc(completed).map(|utc| format!("COMPLETED:{}", utc)));
+            },
+            None => lines.push("STATUS:NEEDS-ACTION".to_string()),
+        }
+        if !entry.tags.is_empty() {
+            let tags = entry.tags.iter().map(|tag| escape_text(tag)).collect::<Vec<_>>();
+            lines.push(format!("CATEGORIES:{}", tags.join(",")));
+        }
+        lines.push("END:VTODO".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold_line(line)).collect()
+}
+
+pub fn parse(contents: &str) -> Vec<TodoEntry> {
+    let mut entries = Vec::new();
+    let mut current: Option<TodoEntry> = None;
+    let mut status_completed = false;
+    // How many components (such as a VALARM) deep inside the VTODO we are;
+    // only the VTODO's own properties describe the task.
+    let mut depth = 0;
+    for line in unfold_lines(contents) {
+        let Some((name_and_params, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = name_and_params.split(';').next().unwrap_or("").to_ascii_uppercase();
+        match (name.as_str(), current.as_mut()) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VTODO") => {
+                current = Some(TodoEntry::default());
+                status_completed = false;
+                depth = 0;
+            },
+            ("BEGIN", Some(_)) => depth += 1,
+            ("END", Some(_)) if depth > 0 => depth -= 1,
+            (_, Some(_)) if depth > 0 => {},
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VTODO") => {
+                let mut entry = current.take().unwrap();
+                if entry.create_date.is_empty() {
+                    entry.create_date = Local::now().format(DATE_FORMAT).to_string();
+                }
+                if status_completed && entry.completed.is_none() {
+                    entry.completed = Some(Local::now().format(DATE_FORMAT).to_string());
+                }
+                entries.push(entry);
+            },
+            ("SUMMARY", Some(entry)) => entry.task_description = unescape_text(value),
+            ("DUE", Some(entry)) => entry.due_date = ical_to_stamp(value).unwrap_or_default(),
+            ("CREATED", Some(entry)) => entry.create_date = ical_to_stamp(value).unwrap_or_default(),
+            ("PRIORITY", Some(entry)) => entry.priority = priority_from_ical(value),
+            ("COMPLETED", Some(entry)) => entry.completed = ical_to_stamp(value),
+            ("STATUS", Some(_)) => status_completed = value.eq_ignore_ascii_case("COMPLETED"),
+            ("CATEGORIES", Some(entry)) => entry.tags.extend(split_list(value)),
+            _ => {},
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_entries() {
+        let entries = vec![
+            TodoEntry {
+                create_date: "202401020930".to_string(),
+                due_date: "202401051830".to_string(),
+                task_description: "Review PR, then merge; a long description that needs folding onto a second line".to_string(),
+                priority: Some('B'),
+                completed: None,
+                tags: vec!["work".to_string(), "@laptop".to_string()],
+            },
+            TodoEntry {
+                create_date: "202312010000".to_string(),
+                due_date: "202312030000".to_string(),
+                task_description: "Renew passport".to_string(),
+                priority: None,
+                completed: Some("202312021015".to_string()),
+                tags: vec![],
+            },
+        ];
+        let rendered = render(&entries);
+        assert!(rendered.lines().all(|line| line.len() <= MAX_LINE_OCTETS));
+        assert_eq!(parse(&rendered), entries);
+    }
+
+    #[test]
+    fn parses_date_only_due() {
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nSUMMARY:Taxes\r\nDUE;VALUE=DATE:20240415\r\nPRIORITY:1\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        let entries = parse(ics);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].due_date, "202404150000");
+        assert_eq!(entries[0].priority, Some('A'));
+    }
+
+    #[test]
+    fn ignores_properties_of_nested_components() {
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nSUMMARY:Taxes\r\nBEGIN:VALARM\r\nACTION:DISPLAY\r\nSUMMARY:Reminder\r\nEND:VALARM\r\nPRIORITY:1\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        let entries = parse(ics);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].task_description, "Taxes");
+        assert_eq!(entries[0].priority, Some('A'));
+    }
+
+    #[test]
+    fn uids_are_stable() {
+        let entry = TodoEntry { create_date: "202401020930".to_string(), task_description: "Renew passport".to_string(), ..Default::default() };
+        assert_eq!(uid_for(&entry), "202401020930-e5677ad50fa9cf84@simple_todo");
+    }
+}
@@ -0,0 +1,292 @@
+//! Named todo lists kept under a data directory, and the config file that
+//! says which one to use by default.
+//!
+//! Lists live in `<data dir>/lists/<name>.todo` using the same row format as
+//! `TODO_SUPPORT_FILE`; archived lists move to `<data dir>/archive/`. The
+//! config is `<config dir>/simple_todo/config.toml`:
+//!
+//! ```toml
+//! default_list = "work"
+//! # data_dir = "/somewhere/else"
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{history, read_todos, write_todos, TODO_SUPPORT_FILE};
+
+const APP_DIR: &str = "simple_todo";
+const CONFIG_FILE: &str = "config.toml";
+const LIST_EXTENSION: &str = "todo";
+const FIRST_LIST: &str = "todo";
+
+#[derive(clap::Subcommand, Clone, Debug)]
+pub enum ListAction {
+    /// Create an empty list
+    New { name: String },
+    /// Rename a list, keeping its tasks
+    Rename { from: String, to: String },
+    /// Move a list out of the way without deleting it
+    Archive { name: String },
+    /// Use this list when `--list` isn't given
+    Default { name: String },
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct Config {
+    pub default_list: Option<String>,
+    pub data_dir: Option<PathBuf>,
+}
+
+fn config_path() -> Result<PathBuf, String> {
+    dirs::config_dir()
+        .map(|dir| dir.join(APP_DIR).join(CONFIG_FILE))
+        .ok_or_else(|| "Could not work out a config directory; set XDG_CONFIG_HOME or HOME".to_string())
+}
+
+impl Config {
+    fn load(path: &Path) -> Result<Option<Config>, String> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("Error reading {}: {}", path.display(), error))?;
+        toml::from_str(&contents)
+            .map(Some)
+            .map_err(|error| format!("Error parsing {}: {}", path.display(), error))
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|error| format!("Error creating {}: {}", parent.display(), error))?;
+        }
+        let contents = toml::to_string(self).map_err(|error| format!("Error writing config: {}", error))?;
+        fs::write(path, contents).map_err(|error| format!("Error writing {}: {}", path.display(), error))
+    }
+
+    fn data_dir(&self) -> Result<PathBuf, String> {
+        match &self.data_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => dirs::data_dir()
+                .map(|dir| dir.join(APP_DIR))
+                .ok_or_else(|| "Could not work out a data directory; set data_dir in the config".to_string()),
+        }
+    }
+}
+
+/// The config and data directory every list operation works against.
+pub struct Lists {
+    config: Config,
+    config_path: PathBuf,
+    data_dir: PathBuf,
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        Err(format!("'{}' is not a valid list name", name))
+    } else {
+        Ok(())
+    }
+}
+
+impl Lists {
+    /// Loads the config, writing a starter one and saying so on first run.
+    pub fn load() -> Result<Lists, String> {
+        let config_path = config_path()?;
+        let config = match Config::load(&config_path)? {
+            Some(config) => config,
+            None => {
+                let config = Config { default_list: Some(FIRST_LIST.to_string()), data_dir: None };
+                config.save(&config_path)?;
+                // On stderr, so it doesn't end up in the output of `remind`
+                // in a shell prompt.
+                eprintln!(
+                    "Welcome to simple_todo! Your tasks go in the '{}' list under {}.\n\
+                     Change the default list or data directory in {}.\n",
+                    FIRST_LIST,
+                    config.data_dir()?.display(),
+                    config_path.display(),
+                );
+                config
+            },
+        };
+        let data_dir = config.data_dir()?;
+        Ok(Lists { config, config_path, data_dir })
+    }
+
+    fn lists_dir(&self) -> PathBuf {
+        self.data_dir.join("lists")
+    }
+
+    fn archive_dir(&self) -> PathBuf {
+        self.data_dir.join("archive")
+    }
+
+    fn path_for(&self, name: &str) -> PathBuf {
+        self.lists_dir().join(format!("{}.{}", name, LIST_EXTENSION))
+    }
+
+    fn existing_path_for(&self, name: &str) -> Result<PathBuf, String> {
+        validate_name(name)?;
+        let path = self.path_for(name);
+        if path.exists() {
+            Ok(path)
+        } else {
+            Err(format!("There is no list called '{}'; create it with `simple_todo lists new {}`", name, name))
+        }
+    }
+
+    fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(self.lists_dir())
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|ext| ext == LIST_EXTENSION))
+                    .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+
+    /// Works out which file to use: `--list`, which must already exist so a
+    /// typo doesn't start a new list, then `TODO_SUPPORT_FILE` for setups
+    /// that predate lists, then the configured default list.
+    pub fn resolve(&self, list: Option<&str>) -> Result<String, String> {
+        let path = match (list, std::env::var(TODO_SUPPORT_FILE)) {
+            (Some(name), _) => return self.existing_path_for(name).map(|path| path.to_string_lossy().to_string()),
+            (None, Ok(filename)) => return Ok(filename),
+            (None, Err(_)) => {
+                let name = self.config.default_list.as_deref().unwrap_or(FIRST_LIST);
+                validate_name(name)?;
+                self.path_for(name)
+            },
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|error| format!("Error creating {}: {}", parent.display(), error))?;
+        }
+        Ok(path.to_string_lossy().to_string())
+    }
+
+    pub fn show(&self) {
+        let default = self.config.default_list.as_deref().unwrap_or(FIRST_LIST);
+        let names = self.names();
+        if names.is_empty() {
+            println!("No lists yet in {}", self.lists_dir().display());
+        }
+        for name in names {
+            let count = read_todos(&self.path_for(&name).to_string_lossy()).len();
+            let marker = if name == default { " (default)" } else { "" };
+            println!("{}{}: {} task(s)", name, marker, count);
+        }
+    }
+
+    pub fn run(&mut self, action: ListAction) -> Result<(), String> {
+        match action {
+            ListAction::New { name } => {
+                validate_name(&name)?;
+                let path = self.path_for(&name);
+                if path.exists() {
+                    return Err(format!("A list called '{}' already exists", name));
+                }
+                fs::create_dir_all(self.lists_dir()).map_err(|error| error.to_string())?;
+                fs::write(&path, "").map_err(|error| format!("Error writing {}: {}", path.display(), error))?;
+                println!("Created list '{}'", name);
+            },
+            ListAction::Rename { from, to } => {
+                let from_path = self.existing_path_for(&from)?;
+                validate_name(&to)?;
+                let to_path = self.path_for(&to);
+                if to_path.exists() {
+                    return Err(format!("A list called '{}' already exists", to));
+                }
+                fs::rename(&from_path, &to_path).map_err(|error| format!("Error renaming list: {}", error))?;
+                history::move_log(&from_path, &to_path).map_err(|error| format!("Error moving list log: {}", error))?;
+                if self.config.default_list.as_deref() == Some(from.as_str()) {
+                    self.config.default_list = Some(to.clone());
+                    self.config.save(&self.config_path)?;
+                }
+                println!("Renamed list '{}' to '{}'", from, to);
+            },
+            ListAction::Archive { name } => {
+                let path = self.existing_path_for(&name)?;
+                if self.config.default_list.as_deref() == Some(name.as_str()) {
+                    return Err(format!("'{}' is the default list; pick another default first", name));
+                }
+                let archive_dir = self.archive_dir();
+                fs::create_dir_all(&archive_dir).map_err(|error| error.to_string())?;
+                let stamp = chrono::Local::now().format(crate::DATE_FORMAT);
+                let archived = archive_dir.join(format!("{}-{}.{}", name, stamp, LIST_EXTENSION));
+                fs::rename(&path, &archived).map_err(|error| format!("Error archiving list: {}", error))?;
+                history::move_log(&path, &archived).map_err(|error| format!("Error moving list log: {}", error))?;
+                println!("Archived list '{}' to {}", name, archived.display());
+            },
+            ListAction::Default { name } => {
+                self.existing_path_for(&name)?;
+                self.config.default_list = Some(name.clone());
+                self.config.save(&self.config_path)?;
+                println!("'{}' is now the default list", name);
+            },
+        }
+        Ok(())
+    }
+
+    /// Moves the `number`th task (as numbered by `show`) from `filename` to the list `to`.
+    pub fn move_task(&self, filename: &str, number: usize, to: &str) -> Result<(), String> {
+        let to_path = self.existing_path_for(to)?.to_string_lossy().to_string();
+        if to_path == filename {
+            return Err(format!("That task is already in '{}'", to));
+        }
+        let mut from_entries = read_todos(filename);
+        if number == 0 || number > from_entries.len() {
+            return Err(format!("There is no task #{}; `show` lists their numbers", number));
+        }
+        let task = from_entries.remove(number - 1);
+        let mut to_entries = read_todos(&to_path);
+        println!("Moving '{}' to '{}'", task.task_description, to);
+        to_entries.push(task);
+        to_entries.sort_by(|a, b| a.due_date.cmp(&b.due_date));
+        write_todos(&to_path, to_entries, &format!("{} {}", history::MOVE_IN_FROM, filename))
+            .map_err(|error| format!("Error writing {}: {}", to_path, error))?;
+        write_todos(filename, from_entries, &format!("{} {}", history::MOVE_TO, to)).map_err(|error| format!("Error writing {}: {}", filename, error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_list_names() {
+        assert!(validate_name("work").is_ok());
+        assert!(validate_name("release-2.0").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../etc").is_err());
+        assert!(validate_name(".hidden").is_err());
+    }
+
+    #[test]
+    fn resolves_only_existing_lists() {
+        let data_dir = std::env::temp_dir().join(format!("simple_todo-lists-{}", std::process::id()));
+        let mut lists = Lists { config: Config::default(), config_path: data_dir.join(CONFIG_FILE), data_dir: data_dir.clone() };
+        assert!(lists.resolve(Some("wrok")).unwrap_err().contains("no list called 'wrok'"));
+        assert!(!lists.path_for("wrok").exists());
+        lists.run(ListAction::New { name: "work".to_string() }).unwrap();
+        assert_eq!(lists.resolve(Some("work")).unwrap(), lists.path_for("work").to_string_lossy());
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn config_round_trips_through_toml() {
+        let config = Config { default_list: Some("home".to_string()), data_dir: Some(PathBuf::from("/tmp/todos")) };
+        let parsed: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(parsed, config);
+        assert_eq!(toml::from_str::<Config>("").unwrap(), Config::default());
+    }
+}
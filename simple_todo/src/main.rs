@@ -1,80 +1,285 @@
-use std::{env, fmt};
+use std::{fmt, process};
 use std::fs::read_to_string;
 use std::io::stdin;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use chrono::prelude::*;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+
+use lists::{ListAction, Lists};
+
+mod history;
+mod ical;
+mod lists;
+mod remind;
+mod todo_txt;
+mod tui;
 
 const TODO_SUPPORT_FILE: &str = "TODO_SUPPORT_FILE";
+const DATE_FORMAT: &str = "%Y%m%d%H%M";
 
-#[derive(clap::ValueEnum, Clone, Debug)]
+#[derive(Subcommand, Clone, Debug)]
 enum Command {
     Add,
     CleanUp,
     Show,
+    /// Full-screen view of the list with keybindings to add, complete, edit, snooze and filter
+    Tui,
+    /// Report tasks overdue or due within a window; exits 0 if none, 3 if some are due soon, 4 if any are overdue
+    Remind {
+        /// How far ahead to look, e.g. 30m, 2h, 3d or 1w
+        #[arg(short, long, default_value = "24h", value_parser = remind::parse_window)]
+        within: chrono::Duration,
+        #[command(flatten)]
+        notify: remind::NotifyArgs,
+    },
+    /// Keep running and send a reminder as each task comes due
+    Watch {
+        /// How long before the due time to fire, e.g. 15m
+        #[arg(short, long, default_value = "0m", value_parser = remind::parse_window)]
+        lead: chrono::Duration,
+        /// Seconds between re-reading the todo file
+        #[arg(short, long, default_value_t = 30)]
+        interval: u64,
+        #[command(flatten)]
+        notify: remind::NotifyArgs,
+    },
+    /// Show all lists, or create, rename, archive or pick the default list
+    Lists {
+        #[command(subcommand)]
+        action: Option<ListAction>,
+    },
+    /// Move a task, by the number `show` gives it, to another list
+    Move {
+        number: usize,
+        to: String,
+    },
+    /// Revert the last N logged changes to the list
+    Undo {
+        #[arg(default_value_t = 1)]
+        count: usize,
+        /// Undo even if the list was changed outside simple_todo since, losing those changes
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show what changed when, newest first
+    Log {
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Merge tasks from a todo.txt or iCalendar (.ics) file into the todo file
+    Import {
+        path: PathBuf,
+        #[arg(short, long)]
+        format: Option<ExchangeFormat>,
+    },
+    /// Write the todo file out as todo.txt or iCalendar (.ics)
+    Export {
+        path: PathBuf,
+        #[arg(short, long)]
+        format: Option<ExchangeFormat>,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum ExchangeFormat {
+    TodoTxt,
+    Ical,
+}
+
+impl ExchangeFormat {
+    fn from_path(path: &Path) -> ExchangeFormat {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ics") | Some("ical") => ExchangeFormat::Ical,
+            _ => ExchangeFormat::TodoTxt,
+        }
+    }
 }
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    command: Command, 
+    /// Which list to use instead of the default one
+    #[arg(short, long, global = true)]
+    list: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
 }
 
+#[derive(Clone, Debug, Default, PartialEq)]
 struct TodoEntry {
     create_date: String, // in YYYYmmddhhmm form
     due_date: String, // in YYYYmmddhhmm form
     task_description: String,
+    priority: Option<char>, // 'A' is the highest
+    completed: Option<String>, // in YYYYmmddhhmm form
+    tags: Vec<String>,
 }
 
 impl TodoEntry {
+    fn has_extras(&self) -> bool {
+        self.priority.is_some() || self.completed.is_some() || !self.tags.is_empty()
+    }
+
     fn make_row(&self) -> String {
-        format!("{}|>{}|>{}", self.create_date, self.task_description, self.due_date)
+        let row = format!("{}|>{}|>{}", self.create_date, self.task_description, self.due_date);
+        if !self.has_extras() {
+            return row;
+        }
+        format!(
+            "{}|>{}|>{}|>{}",
+            row,
+            self.priority.map(String::from).unwrap_or_default(),
+            self.completed.clone().unwrap_or_default(),
+            self.tags.join(","),
+        )
     }
 
     fn from_row(todo_row: String) -> Option<TodoEntry> {
         let pieces: Vec<&str> = todo_row.split("|>").collect();
-        if pieces.len() != 3 {
+        if pieces.len() != 3 && pieces.len() != 6 {
             println!("skipping invalid todo row: {}", todo_row);
             None
         } else {
-            Some(TodoEntry {
+            let mut entry = TodoEntry {
                 create_date: pieces[0].to_string(),
                 task_description: pieces[1].to_string(),
                 due_date: pieces[2].to_string(),
-            })
+                ..Default::default()
+            };
+            if pieces.len() == 6 {
+                entry.priority = pieces[3].chars().next();
+                entry.completed = Some(pieces[4].to_string()).filter(|c| !c.is_empty());
+                entry.tags = pieces[5]
+                    .split(',')
+                    .filter(|tag| !tag.is_empty())
+                    .map(|tag| tag.to_string())
+                    .collect();
+            }
+            Some(entry)
         }
     }
+
+    /// Why the entry can't be stored as a row, if it can't: the description
+    /// and tags can't hold the `|>` separator or a line break, and tags can't
+    /// hold the `,` between them.
+    fn row_problem(&self) -> Option<String> {
+        let breaks_row = |text: &str| text.contains("|>") || text.contains('\n') || text.contains('\r');
+        if breaks_row(&self.task_description) {
+            return Some("the description contains \"|>\" or a line break".to_string());
+        }
+        self.tags
+            .iter()
+            .find(|tag| breaks_row(tag) || tag.contains(','))
+            .map(|tag| format!("the tag {:?} contains \",\", \"|>\" or a line break", tag))
+    }
+
+    fn due(&self) -> Option<NaiveDateTime> {
+        NaiveDateTime::parse_from_str(&self.due_date, DATE_FORMAT).ok()
+    }
+
+    /// Two entries are the same task if they share a description and due date,
+    /// which is what import uses to avoid adding duplicates.
+    fn same_task(&self, other: &TodoEntry) -> bool {
+        self.task_description == other.task_description && self.due_date == other.due_date
+    }
 }
 
 impl fmt::Display for TodoEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "({})\n{}\n[Due: {}]\n===\n", self.create_date, self.task_description, self.due_date)
+        write!(f, "({})", self.create_date)?;
+        if let Some(priority) = self.priority {
+            write!(f, " ({})", priority)?;
+        }
+        if let Some(completed) = &self.completed {
+            write!(f, " [Done: {}]", completed)?;
+        }
+        write!(f, "\n{}\n[Due: {}]\n", self.task_description, self.due_date)?;
+        if !self.tags.is_empty() {
+            writeln!(f, "[Tags: {}]", self.tags.join(", "))?;
+        }
+        writeln!(f, "===")
     }
 }
 
-fn show_todo(filename: String) {
-    // check if file exists if not - alert
-    if !Path::new(&filename).exists() {
-        println!("The file, {}, does not exist!", filename);
-    } else {
-        let mut todos_to_show: Vec<TodoEntry> = Vec::new();
+fn read_todos(filename: &str) -> Vec<TodoEntry> {
+    let mut todo_entries: Vec<TodoEntry> = Vec::new();
+    if Path::new(filename).exists() {
         match read_to_string(filename) {
             Err(error) => { println!("Error reading file: {}", error) },
             Ok(contents) => {
-                let todo_rows: Vec<&str> = contents.split('\n').collect();
-                for row in todo_rows {
-                    match TodoEntry::from_row(row.trim().to_string()){
-                        None => {},
-                        Some(todo) => todos_to_show.push(todo),
+                for row in contents.split('\n') {
+                    if row.trim().is_empty() {
+                        continue;
+                    }
+                    if let Some(todo) = TodoEntry::from_row(row.trim().to_string()) {
+                        todo_entries.push(todo);
                     }
                 }
             }
         }
+    }
+    todo_entries
+}
+
+fn import_todos(filename: String, path: PathBuf, format: Option<ExchangeFormat>) {
+    let contents = match read_to_string(&path) {
+        Err(error) => {
+            println!("Error reading {}: {}", path.display(), error);
+            return;
+        },
+        Ok(contents) => contents,
+    };
+    let imported = match format.unwrap_or_else(|| ExchangeFormat::from_path(&path)) {
+        ExchangeFormat::TodoTxt => todo_txt::parse(&contents),
+        ExchangeFormat::Ical => ical::parse(&contents),
+    };
+    let mut todo_entries = read_todos(&filename);
+    let mut added = 0;
+    for entry in imported {
+        if todo_entries.iter().any(|existing| existing.same_task(&entry)) {
+            continue;
+        }
+        if let Some(problem) = entry.row_problem() {
+            println!("Skipping {:?}: {}", entry.task_description, problem);
+            continue;
+        }
+        todo_entries.push(entry);
+        added += 1;
+    }
+    todo_entries.sort_by(|a,b| a.due_date.cmp(&b.due_date));
+    write_todos(&filename, todo_entries, "import").unwrap();
+    println!("Imported {} task(s) from {}", added, path.display());
+}
+
+fn export_todos(filename: String, path: PathBuf, format: Option<ExchangeFormat>) {
+    let todo_entries = read_todos(&filename);
+    let count = todo_entries.len();
+    let contents = match format.unwrap_or_else(|| ExchangeFormat::from_path(&path)) {
+        ExchangeFormat::TodoTxt => todo_txt::render(&todo_entries),
+        ExchangeFormat::Ical => ical::render(&todo_entries),
+    };
+    match std::fs::write(&path, contents) {
+        Err(error) => println!("Error writing {}: {}", path.display(), error),
+        Ok(_) => println!("Exported {} task(s) to {}", count, path.display()),
+    }
+}
+
+fn show_todo(filename: String) {
+    // check if file exists if not - alert
+    if !Path::new(&filename).exists() {
+        println!("The file, {}, does not exist yet - add a task to start it.", filename);
+    } else {
+        let todos_to_show = read_todos(&filename);
         if todos_to_show.is_empty() {
             println!("No Todo's to show.");
         } else {
-            let lines = todos_to_show.iter().map(|todo| format!("{}", todo)).collect::<Vec<_>>();
+            let lines = todos_to_show
+                .iter()
+                .enumerate()
+                .map(|(index, todo)| format!("#{} {}", index + 1, todo))
+                .collect::<Vec<_>>();
             let to_print = lines.join("\n");
             println!("{to_print}")
         }
@@ -84,61 +289,42 @@ fn show_todo(filename: String) {
 fn clean_up(filename: String) {
     let now = Local::now();
     let mut todo_entries: Vec<TodoEntry> = Vec::new();
-    if Path::new(&filename).exists() {
-        match read_to_string(&filename) {
-            Err(error) => { println!("Error reading file: {}", error) },
-            Ok(contents) => {
-                let todo_rows: Vec<&str> = contents.split('\n').collect();
-                for row in todo_rows {
-                    match TodoEntry::from_row(row.trim().to_string()){
-                        None => {},
-                        Some(todo) => {
-                            let due_date = NaiveDateTime::parse_from_str(&todo.due_date, "%Y%m%d%H%M").unwrap();
-                            if due_date < now.naive_local() {
-                                println!("Was {} compeleted? (y/n)", todo.task_description);
-                                let mut response = String::new();
-                                stdin().read_line(&mut response).unwrap();
-                                if response.trim() == "y" {
-                                    println!("Cleaning up: {}", todo.task_description);
-                                } else {
-                                    todo_entries.push(todo);
-                                }
-                            } else {
-                                todo_entries.push(todo);
-                            }
-                        },
-                    }
-                }
+    for todo in read_todos(&filename) {
+        let overdue = todo.due().is_some_and(|due_date| due_date < now.naive_local());
+        if todo.completed.is_some() || overdue {
+            if todo.completed.is_some() {
+                println!("Remove completed {}? (y/n)", todo.task_description);
+            } else {
+                println!("Was {} compeleted? (y/n)", todo.task_description);
             }
+            let mut response = String::new();
+            stdin().read_line(&mut response).unwrap();
+            if response.trim() == "y" {
+                println!("Cleaning up: {}", todo.task_description);
+            } else {
+                todo_entries.push(todo);
+            }
+        } else {
+            todo_entries.push(todo);
         }
     }
 
-    let contents = todos_to_print(todo_entries);
-    std::fs::write(&filename, contents).unwrap();
+    write_todos(&filename, todo_entries, "clean-up").unwrap();
 }
 
 fn todos_to_print(todos: Vec<TodoEntry>) -> String {
     todos.into_iter().map(|t| t.make_row()).collect::<Vec<String>>().join("\n")
 }
 
+/// Atomically replaces the file and logs the change as `operation` so it can be undone.
+fn write_todos(filename: &str, todos: Vec<TodoEntry>, operation: &str) -> std::io::Result<()> {
+    history::write_and_record(filename, operation, todos_to_print(todos))
+}
+
 fn add_todo(filename: String) {
     let now = Local::now();
-    let now_string = now.format("%Y%m%d%H%M").to_string();
-    let mut todo_entries: Vec<TodoEntry> = Vec::new();
-    if Path::new(&filename).exists() {
-        match read_to_string(&filename) {
-            Err(error) => { println!("Error reading file: {}", error) },
-            Ok(contents) => {
-                let todo_rows: Vec<&str> = contents.split('\n').collect();
-                for row in todo_rows {
-                    match TodoEntry::from_row(row.trim().to_string()){
-                        None => {},
-                        Some(todo) => { todo_entries.push(todo) },
-                    }
-                }
-            }
-        }
-    }
+    let now_string = now.format(DATE_FORMAT).to_string();
+    let mut todo_entries = read_todos(&filename);
 
     println!("Enter task description:");
     let mut description: String = String::new();
@@ -152,24 +338,64 @@ fn add_todo(filename: String) {
         create_date: now_string,
         task_description: description.trim().to_string(),
         due_date: due.trim().to_string(),
+        ..Default::default()
     };
+    if let Some(problem) = new_entry.row_problem() {
+        println!("Can't add the task: {}", problem);
+        return;
+    }
     todo_entries.push(new_entry);
     todo_entries.sort_by(|a,b| a.due_date.cmp(&b.due_date));
-    let contents = todos_to_print(todo_entries);
-    std::fs::write(&filename, contents).unwrap();
+    write_todos(&filename, todo_entries, "add").unwrap();
 }
 
 fn main() {
-    match env::var(TODO_SUPPORT_FILE) {
-        Err(error) => { panic!("TODO_SUPPORT_FILE env var needs to be set: {}", error) },
-        Ok(filename) => {
-           let args: Args = Args::parse();
-            match args.command {
-                Command::Show => show_todo(filename),
-                Command::Add => add_todo(filename),
-                Command::CleanUp => clean_up(filename),
-           }
-        }
+    let args: Args = Args::parse();
+    let mut lists = match Lists::load() {
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        },
+        Ok(lists) => lists,
+    };
+    let filename = match lists.resolve(args.list.as_deref()) {
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        },
+        Ok(filename) => filename,
+    };
+    match args.command {
+        Command::Show => show_todo(filename),
+        Command::Add => add_todo(filename),
+        Command::CleanUp => clean_up(filename),
+        Command::Tui => tui::run(filename),
+        Command::Remind { within, notify } => remind::remind(filename, within, notify),
+        Command::Watch { lead, interval, notify } => {
+            remind::watch(filename, lead, std::time::Duration::from_secs(interval), notify)
+        },
+        Command::Import { path, format } => import_todos(filename, path, format),
+        Command::Export { path, format } => export_todos(filename, path, format),
+        Command::Undo { count, force } => {
+            if let Err(error) = history::undo(&filename, count, force) {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        },
+        Command::Log { limit } => history::show_log(&filename, limit),
+        Command::Lists { action: None } => lists.show(),
+        Command::Lists { action: Some(action) } => {
+            if let Err(error) = lists.run(action) {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        },
+        Command::Move { number, to } => {
+            if let Err(error) = lists.move_task(&filename, number, &to) {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        },
     }
 }
 
@@ -187,6 +413,17 @@ mod tests {
         assert_eq!(result, 4);
     }
 
+    #[test]
+    fn rejects_entries_that_would_break_the_row() {
+        let entry = TodoEntry { task_description: "a |> b".to_string(), ..Default::default() };
+        assert!(entry.row_problem().is_some());
+        let entry = TodoEntry { task_description: "fine".to_string(), tags: vec!["a,b".to_string()], ..Default::default() };
+        assert!(entry.row_problem().is_some());
+        let entry = TodoEntry { task_description: "fine | >".to_string(), tags: vec!["@home".to_string()], ..Default::default() };
+        assert_eq!(entry.row_problem(), None);
+        assert_eq!(TodoEntry::from_row(entry.make_row()), Some(entry));
+    }
+
     #[test]
     fn sorting_works() {
         let mut todos = vec!(
@@ -194,21 +431,25 @@ mod tests {
               create_date: "xxx".to_string(),
               due_date: "999".to_string(),
               task_description: "desc".to_string(),
+              ..Default::default()
             },
             TodoEntry {
               create_date: "xxx".to_string(),
               due_date: "909".to_string(),
               task_description: "desc".to_string(),
+              ..Default::default()
             },
             TodoEntry {
               create_date: "xxx".to_string(),
               due_date: "109".to_string(),
               task_description: "desc".to_string(),
+              ..Default::default()
             },
             TodoEntry {
               create_date: "xxx".to_string(),
               due_date: "666".to_string(),
               task_description: "desc".to_string(),
+              ..Default::default()
             },
         );
         let expected_todos = vec!(
@@ -216,21 +457,25 @@ mod tests {
               create_date: "xxx".to_string(),
               due_date: "109".to_string(),
               task_description: "desc".to_string(),
+              ..Default::default()
             },
             TodoEntry {
               create_date: "xxx".to_string(),
               due_date: "666".to_string(),
               task_description: "desc".to_string(),
+              ..Default::default()
             },
             TodoEntry {
               create_date: "xxx".to_string(),
               due_date: "909".to_string(),
               task_description: "desc".to_string(),
+              ..Default::default()
             },
             TodoEntry {
               create_date: "xxx".to_string(),
               due_date: "999".to_string(),
               task_description: "desc".to_string(),
+              ..Default::default()
             },
         );
         todos.sort_by(|a,b| a.due_date.cmp(&b.due_date));
@@ -0,0 +1,244 @@
+//! Due-soon reminders, either as a one-shot check (`remind`) for shell
+//! prompts and cron, or as a long-running `watch` that fires as tasks come due.
+
+use std::collections::HashSet;
+use std::process::{self, Command};
+use std::thread;
+
+use chrono::prelude::*;
+use chrono::Duration;
+use serde_json::json;
+
+use crate::{read_todos, TodoEntry};
+
+/// Exit status of `remind` when nothing is overdue or due within the window.
+pub const EXIT_NOTHING_DUE: i32 = 0;
+/// Exit status of `remind` when tasks are due within the window but none are overdue.
+pub const EXIT_DUE_SOON: i32 = 3;
+/// Exit status of `remind` when at least one task is overdue.
+pub const EXIT_OVERDUE: i32 = 4;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum NotifyTarget {
+    Stdout,
+    Desktop,
+    Webhook,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct NotifyArgs {
+    /// Where to send reminders; can be given more than once
+    #[arg(short, long, value_enum, default_values_t = vec![NotifyTarget::Stdout])]
+    notify: Vec<NotifyTarget>,
+
+    /// URL that `--notify webhook` POSTs a JSON payload to
+    #[arg(long, env = "TODO_REMIND_WEBHOOK")]
+    webhook_url: Option<String>,
+}
+
+struct Reminder {
+    entry: TodoEntry,
+    due: NaiveDateTime,
+    overdue: bool,
+}
+
+impl Reminder {
+    fn summary(&self) -> String {
+        let when = self.due.format("%Y-%m-%d %H:%M");
+        if self.overdue {
+            format!("OVERDUE since {}: {}", when, self.entry.task_description)
+        } else {
+            format!("Due {}: {}", when, self.entry.task_description)
+        }
+    }
+}
+
+/// Parses a window like `90m`, `2h`, `3d` or `1w`; a bare number is hours.
+pub fn parse_window(window: &str) -> Result<Duration, String> {
+    let window = window.trim();
+    let split = window.find(|c: char| !c.is_ascii_digit()).unwrap_or(window.len());
+    let (amount, unit) = window.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| format!("invalid window '{}', expected e.g. 30m, 2h, 3d or 1w", window))?;
+    let duration = match unit {
+        "m" => Duration::try_minutes(amount),
+        "" | "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => return Err(format!("invalid window unit '{}', expected m, h, d or w", unit)),
+    };
+    duration
+        .filter(|duration| Local::now().naive_local().checked_add_signed(*duration).is_some())
+        .ok_or_else(|| format!("window '{}' is too long", window))
+}
+
+/// Open tasks due before `now + window`, earliest first.
+fn due_within(entries: Vec<TodoEntry>, now: NaiveDateTime, window: Duration) -> Vec<Reminder> {
+    let until = now.checked_add_signed(window).unwrap_or(NaiveDateTime::MAX);
+    let mut reminders: Vec<Reminder> = entries
+        .into_iter()
+        .filter(|entry| entry.completed.is_none())
+        .filter_map(|entry| {
+            let due = entry.due()?;
+            (due <= until).then_some(Reminder { entry, due, overdue: due < now })
+        })
+        .collect();
+    reminders.sort_by_key(|reminder| reminder.due);
+    reminders
+}
+
+fn exit_status(reminders: &[Reminder]) -> i32 {
+    if reminders.iter().any(|reminder| reminder.overdue) {
+        EXIT_OVERDUE
+    } else if reminders.is_empty() {
+        EXIT_NOTHING_DUE
+    } else {
+        EXIT_DUE_SOON
+    }
+}
+
+/// `text` as an AppleScript string literal, in which only `\` and `"` need
+/// escaping.
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn notify_desktop(reminders: &[Reminder]) -> Result<(), String> {
+    let title = format!("simple_todo: {} task(s) due", reminders.len());
+    let body = reminders.iter().map(Reminder::summary).collect::<Vec<_>>().join("\n");
+    let status = if cfg!(target_os = "macos") {
+        let script =
+            format!("display notification {} with title {}", applescript_string(&body), applescript_string(&title));
+        Command::new("osascript").args(["-e", &script]).status()
+    } else {
+        Command::new("notify-send").args([&title, &body]).status()
+    };
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("desktop notifier exited with {}", status)),
+        Err(error) => Err(format!("unable to run desktop notifier: {}", error)),
+    }
+}
+
+fn notify_webhook(url: &str, reminders: &[Reminder]) -> Result<(), String> {
+    let tasks: Vec<_> = reminders
+        .iter()
+        .map(|reminder| {
+            json!({
+                "description": reminder.entry.task_description,
+                "due": reminder.due.format("%Y-%m-%dT%H:%M:00").to_string(),
+                "overdue": reminder.overdue,
+                "priority": reminder.entry.priority.map(String::from),
+                "tags": reminder.entry.tags,
+            })
+        })
+        .collect();
+    // `text` makes the payload usable as-is by Slack-style incoming webhooks
+    let payload = json!({
+        "text": reminders.iter().map(Reminder::summary).collect::<Vec<_>>().join("\n"),
+        "tasks": tasks,
+    });
+    ureq::post(url)
+        .header("Content-Type", "application/json")
+        .send(payload.to_string())
+        .map(|_| ())
+        .map_err(|error| format!("unable to post to webhook {}: {}", url, error))
+}
+
+fn send(args: &NotifyArgs, reminders: &[Reminder]) {
+    if reminders.is_empty() {
+        return;
+    }
+    for target in &args.notify {
+        let result = match target {
+            NotifyTarget::Stdout => {
+                reminders.iter().for_each(|reminder| println!("{}", reminder.summary()));
+                Ok(())
+            },
+            NotifyTarget::Desktop => notify_desktop(reminders),
+            NotifyTarget::Webhook => match &args.webhook_url {
+                Some(url) => notify_webhook(url, reminders),
+                None => Err("--notify webhook needs --webhook-url or TODO_REMIND_WEBHOOK".to_string()),
+            },
+        };
+        if let Err(error) = result {
+            eprintln!("{}", error);
+        }
+    }
+}
+
+/// Reports tasks overdue or due within `window` and exits with a status a
+/// shell prompt or cron job can branch on.
+pub fn remind(filename: String, window: Duration, args: NotifyArgs) {
+    let now = Local::now().naive_local();
+    let reminders = due_within(read_todos(&filename), now, window);
+    send(&args, &reminders);
+    process::exit(exit_status(&reminders));
+}
+
+/// Re-reads the file every `interval` and fires once per task when it is
+/// `lead` away from its due time, so edits made while running are picked up.
+pub fn watch(filename: String, lead: Duration, interval: std::time::Duration, args: NotifyArgs) {
+    let mut fired: HashSet<(String, String)> = HashSet::new();
+    loop {
+        let now = Local::now().naive_local();
+        let reminders: Vec<Reminder> = due_within(read_todos(&filename), now, lead)
+            .into_iter()
+            .filter(|reminder| {
+                fired.insert((reminder.entry.task_description.clone(), reminder.entry.due_date.clone()))
+            })
+            .collect();
+        send(&args, &reminders);
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(description: &str, due_date: &str) -> TodoEntry {
+        TodoEntry {
+            create_date: "202401010000".to_string(),
+            due_date: due_date.to_string(),
+            task_description: description.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_windows() {
+        assert_eq!(parse_window("90m"), Ok(Duration::minutes(90)));
+        assert_eq!(parse_window("2"), Ok(Duration::hours(2)));
+        assert_eq!(parse_window("1w"), Ok(Duration::weeks(1)));
+        assert!(parse_window("soon").is_err());
+        assert!(parse_window("3y").is_err());
+        assert!(parse_window("100000000w").is_err());
+        assert!(parse_window("99999999999999999999m").is_err());
+    }
+
+    #[test]
+    fn quotes_applescript_strings() {
+        assert_eq!(applescript_string(r#"Call "Bob" re: C:\temp, don't forget é"#), r#""Call \"Bob\" re: C:\\temp, don't forget é""#);
+    }
+
+    #[test]
+    fn finds_due_and_overdue_tasks() {
+        let now = NaiveDateTime::parse_from_str("202401101200", crate::DATE_FORMAT).unwrap();
+        let mut done = entry("done", "202401091200");
+        done.completed = Some("202401091000".to_string());
+        let entries = vec![
+            entry("later", "202401200000"),
+            entry("soon", "202401101800"),
+            entry("late", "202401091200"),
+            done,
+        ];
+        let reminders = due_within(entries.clone(), now, Duration::hours(24));
+        let names: Vec<_> = reminders.iter().map(|r| r.entry.task_description.as_str()).collect();
+        assert_eq!(names, vec!["late", "soon"]);
+        assert_eq!(exit_status(&reminders), EXIT_OVERDUE);
+        assert_eq!(exit_status(&due_within(entries[..2].to_vec(), now, Duration::hours(24))), EXIT_DUE_SOON);
+        assert_eq!(exit_status(&due_within(entries[..1].to_vec(), now, Duration::hours(1))), EXIT_NOTHING_DUE);
+    }
+}
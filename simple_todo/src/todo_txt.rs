@@ -0,0 +1,172 @@
+//! Conversion between `TodoEntry` rows and the todo.txt format
+//! (<https://github.com/todotxt/todo.txt>).
+//!
+//! todo.txt only has dates, so the due time rides along in a `time:HH:MM`
+//! key whenever it isn't midnight. Tags become `+project` words; `@context`
+//! words are kept as tags with their `@` so they come back out unchanged.
+
+use chrono::prelude::*;
+
+use crate::{TodoEntry, DATE_FORMAT};
+
+const TXT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+fn parse_txt_date(word: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(word, TXT_DATE_FORMAT).ok()
+}
+
+fn to_txt_date(stamp: &str) -> Option<String> {
+    NaiveDateTime::parse_from_str(stamp, DATE_FORMAT)
+        .ok()
+        .map(|time| time.format(TXT_DATE_FORMAT).to_string())
+}
+
+fn date_to_stamp(date: NaiveDate) -> String {
+    date.and_hms_opt(0, 0, 0).unwrap().format(DATE_FORMAT).to_string()
+}
+
+fn parse_priority(word: &str) -> Option<char> {
+    let inner = word.strip_prefix('(')?.strip_suffix(')')?;
+    let mut chars = inner.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), None) if letter.is_ascii_uppercase() => Some(letter),
+        _ => None,
+    }
+}
+
+pub fn parse_line(line: &str) -> Option<TodoEntry> {
+    let mut words = line.split_whitespace().peekable();
+    words.peek()?;
+    let mut entry = TodoEntry::default();
+
+    if words.peek() == Some(&"x") {
+        words.next();
+        let completed = words.peek().and_then(|word| parse_txt_date(word));
+        if completed.is_some() {
+            words.next();
+        }
+        entry.completed = Some(
+            completed
+                .map(date_to_stamp)
+                .unwrap_or_else(|| Local::now().format(DATE_FORMAT).to_string()),
+        );
+    } else if let Some(priority) = words.peek().and_then(|word| parse_priority(word)) {
+        words.next();
+        entry.priority = Some(priority);
+    }
+
+    match words.peek().and_then(|word| parse_txt_date(word)) {
+        Some(created) => {
+            words.next();
+            entry.create_date = date_to_stamp(created);
+        },
+        None => entry.create_date = Local::now().format(DATE_FORMAT).to_string(),
+    }
+
+    let mut due_date: Option<NaiveDate> = None;
+    let mut due_time: Option<NaiveTime> = None;
+    let mut description: Vec<&str> = Vec::new();
+    for word in words {
+        if let Some(project) = word.strip_prefix('+').filter(|p| !p.is_empty()) {
+            entry.tags.push(project.to_string());
+        } else if word.len() > 1 && word.starts_with('@') {
+            entry.tags.push(word.to_string());
+        } else if let Some(date) = word.strip_prefix("due:").and_then(parse_txt_date) {
+            due_date = Some(date);
+        } else if let Some(time) = word
+            .strip_prefix("time:")
+            .and_then(|t| NaiveTime::parse_from_str(t, "%H:%M").ok())
+        {
+            due_time = Some(time);
+        } else if let Some(priority) = word.strip_prefix("pri:").and_then(|p| p.chars().next()) {
+            entry.priority = Some(priority.to_ascii_uppercase());
+        } else {
+            description.push(word);
+        }
+    }
+    entry.task_description = description.join(" ");
+    if let Some(date) = due_date {
+        let time = due_time.unwrap_or_default();
+        entry.due_date = date.and_time(time).format(DATE_FORMAT).to_string();
+    }
+    Some(entry)
+}
+
+pub fn parse(contents: &str) -> Vec<TodoEntry> {
+    contents.lines().filter_map(parse_line).collect()
+}
+
+pub fn render_line(entry: &TodoEntry) -> String {
+    let mut words: Vec<String> = Vec::new();
+    match &entry.completed {
+        Some(completed) => {
+            words.push("x".to_string());
+            words.extend(to_txt_date(completed));
+        },
+        None => words.extend(entry.priority.map(|p| format!("({})", p))),
+    }
+    words.extend(to_txt_date(&entry.create_date));
+    words.push(entry.task_description.clone());
+    for tag in &entry.tags {
+        let tag = tag.split_whitespace().collect::<Vec<_>>().join("-");
+        if tag.starts_with('@') {
+            words.push(tag);
+        } else {
+            words.push(format!("+{}", tag));
+        }
+    }
+    if let Some(due) = entry.due() {
+        words.push(format!("due:{}", due.format(TXT_DATE_FORMAT)));
+        if due.time() != NaiveTime::MIN {
+            words.push(format!("time:{}", due.format("%H:%M")));
+        }
+    }
+    // todo.txt drops the priority from completed tasks, so keep it as a key
+    if let (Some(_), Some(priority)) = (&entry.completed, entry.priority) {
+        words.push(format!("pri:{}", priority));
+    }
+    words.join(" ")
+}
+
+pub fn render(entries: &[TodoEntry]) -> String {
+    entries.iter().map(render_line).map(|line| line + "\n").collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_full_line() {
+        let entry = parse_line("(B) 2024-01-02 Call mum +family @phone due:2024-01-05 time:18:30").unwrap();
+        assert_eq!(entry.priority, Some('B'));
+        assert_eq!(entry.create_date, "202401020000");
+        assert_eq!(entry.task_description, "Call mum");
+        assert_eq!(entry.tags, vec!["family".to_string(), "@phone".to_string()]);
+        assert_eq!(entry.due_date, "202401051830");
+        assert_eq!(entry.completed, None);
+    }
+
+    #[test]
+    fn round_trips_entries() {
+        let entries = vec![
+            TodoEntry {
+                create_date: "202401020000".to_string(),
+                due_date: "202401051830".to_string(),
+                task_description: "Ship the release".to_string(),
+                priority: Some('A'),
+                completed: None,
+                tags: vec!["work".to_string(), "@laptop".to_string()],
+            },
+            TodoEntry {
+                create_date: "202312010000".to_string(),
+                due_date: "202312030000".to_string(),
+                task_description: "Renew passport".to_string(),
+                priority: Some('C'),
+                completed: Some("202312020000".to_string()),
+                tags: vec![],
+            },
+        ];
+        assert_eq!(parse(&render(&entries)), entries);
+    }
+}
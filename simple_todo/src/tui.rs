@@ -0,0 +1,518 @@
+//! Full-screen terminal view of the todo list.
+//!
+//! Every change is written straight back to the list file, so the view
+//! can be left open in a pane and quit at any time without losing anything.
+//! The file is re-read before each change, and whenever it changes while the
+//! view sits idle, so tasks added from elsewhere meanwhile aren't lost.
+
+use std::fs;
+use std::io;
+use std::time::SystemTime;
+
+use chrono::prelude::*;
+use chrono::Duration;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::{read_todos, write_todos, TodoEntry, DATE_FORMAT};
+
+const HELP: &str =
+    "a add  c complete  e edit  s/S snooze day/week  / filter  d delete  q quit";
+const DUE_SOON_HOURS: i64 = 24;
+/// How often to check the file for changes while waiting for a key.
+const REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+enum Prompt {
+    AddDescription,
+    AddDue { description: String },
+    EditDescription { target: TodoEntry },
+    /// The description typed so far is only saved along with the due date.
+    EditDue { target: TodoEntry, description: String },
+    Filter,
+}
+
+impl Prompt {
+    fn label(&self) -> &'static str {
+        match self {
+            Prompt::AddDescription | Prompt::EditDescription { .. } => "Description",
+            Prompt::AddDue { .. } | Prompt::EditDue { .. } => "Due (YYYYMMDDHHMM)",
+            Prompt::Filter => "Filter",
+        }
+    }
+}
+
+fn modified(filename: &str) -> Option<SystemTime> {
+    fs::metadata(filename).and_then(|metadata| metadata.modified()).ok()
+}
+
+struct App {
+    filename: String,
+    entries: Vec<TodoEntry>,
+    /// When the file was last read or written here.
+    modified: Option<SystemTime>,
+    list_state: ListState,
+    filter: String,
+    prompt: Option<Prompt>,
+    input: String,
+    message: String,
+    quit: bool,
+}
+
+impl App {
+    fn new(filename: String) -> App {
+        let entries = read_todos(&filename);
+        let modified = modified(&filename);
+        let mut list_state = ListState::default();
+        if !entries.is_empty() {
+            list_state.select(Some(0));
+        }
+        App {
+            filename,
+            entries,
+            modified,
+            list_state,
+            filter: String::new(),
+            prompt: None,
+            input: String::new(),
+            message: HELP.to_string(),
+            quit: false,
+        }
+    }
+
+    fn matches_filter(&self, entry: &TodoEntry) -> bool {
+        let filter = self.filter.to_lowercase();
+        filter.is_empty()
+            || entry.task_description.to_lowercase().contains(&filter)
+            || entry.tags.iter().any(|tag| tag.to_lowercase().contains(&filter))
+    }
+
+    /// Indexes into `entries` of the tasks the filter lets through.
+    fn visible(&self) -> Vec<usize> {
+        (0..self.entries.len())
+            .filter(|&index| self.matches_filter(&self.entries[index]))
+            .collect()
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        let visible = self.visible();
+        self.list_state.selected().and_then(|row| visible.get(row).copied())
+    }
+
+    fn select_entry(&mut self, entry: &TodoEntry) {
+        let row = self.visible().iter().position(|&index| &self.entries[index] == entry);
+        self.list_state.select(row.or(Some(0)));
+    }
+
+    fn clamp_selection(&mut self) {
+        let count = self.visible().len();
+        match (count, self.list_state.selected()) {
+            (0, _) => self.list_state.select(None),
+            (_, None) => self.list_state.select(Some(0)),
+            (_, Some(row)) if row >= count => self.list_state.select(Some(count - 1)),
+            _ => {},
+        }
+    }
+
+    fn move_selection(&mut self, step: isize) {
+        let count = self.visible().len();
+        if count == 0 {
+            return;
+        }
+        let row = self.list_state.selected().unwrap_or(0) as isize + step;
+        self.list_state.select(Some(row.clamp(0, count as isize - 1) as usize));
+    }
+
+    fn reload(&mut self) {
+        self.entries = read_todos(&self.filename);
+        self.modified = modified(&self.filename);
+    }
+
+    /// Re-reads the file if something else has written it, keeping the
+    /// cursor on the same task.
+    fn refresh(&mut self) {
+        if modified(&self.filename) == self.modified {
+            return;
+        }
+        let selected = self.selected_index().map(|index| self.entries[index].clone());
+        self.reload();
+        match selected {
+            Some(entry) => self.select_entry(&entry),
+            None => self.clamp_selection(),
+        }
+    }
+
+    /// Re-reads the file before a change and finds `target` in it, so the
+    /// change is made to what is on disk now. `None`, with a message, if the
+    /// task was changed or removed elsewhere meanwhile.
+    fn find_fresh(&mut self, target: &TodoEntry) -> Option<usize> {
+        self.reload();
+        let index = self.entries.iter().position(|entry| entry == target);
+        if index.is_none() {
+            self.message = format!("Changed elsewhere, so left alone: {}", target.task_description);
+            self.clamp_selection();
+        }
+        index
+    }
+
+    /// Sorts, writes the file and keeps the cursor on `changed` if it's still there.
+    fn save(&mut self, operation: &str, changed: Option<TodoEntry>) {
+        self.entries.sort_by(|a, b| a.due_date.cmp(&b.due_date));
+        if let Err(error) = write_todos(&self.filename, self.entries.clone(), operation) {
+            self.message = format!("Error writing {}: {}", self.filename, error);
+        }
+        self.modified = modified(&self.filename);
+        match changed {
+            Some(entry) => self.select_entry(&entry),
+            None => self.clamp_selection(),
+        }
+    }
+
+    fn start_prompt(&mut self, prompt: Prompt, input: String) {
+        self.message = String::new();
+        self.prompt = Some(prompt);
+        self.input = input;
+    }
+
+    /// The selected task, as the file has it now.
+    fn fresh_selection(&mut self) -> Option<usize> {
+        let target = self.entries[self.selected_index()?].clone();
+        self.find_fresh(&target)
+    }
+
+    fn toggle_complete(&mut self) {
+        let Some(index) = self.fresh_selection() else { return };
+        let entry = &mut self.entries[index];
+        entry.completed = match entry.completed {
+            Some(_) => None,
+            None => Some(Local::now().format(DATE_FORMAT).to_string()),
+        };
+        let operation = match entry.completed {
+            Some(_) => "complete",
+            None => "reopen",
+        };
+        self.message = format!("{}: {}", operation, entry.task_description);
+        let changed = entry.clone();
+        self.save(operation, Some(changed));
+    }
+
+    fn snooze(&mut self, by: Duration) {
+        let Some(index) = self.fresh_selection() else { return };
+        let now = Local::now().naive_local();
+        let entry = &mut self.entries[index];
+        let from = entry.due().filter(|due| *due > now).unwrap_or(now);
+        entry.due_date = (from + by).format(DATE_FORMAT).to_string();
+        self.message = format!("Snoozed until {}", entry.due_date);
+        let changed = entry.clone();
+        self.save("snooze", Some(changed));
+    }
+
+    fn delete(&mut self) {
+        let Some(index) = self.fresh_selection() else { return };
+        let removed = self.entries.remove(index);
+        self.message = format!("Deleted: {}", removed.task_description);
+        self.save("delete", None);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            if key.code == KeyCode::Char('c') {
+                self.quit = true;
+            }
+            return;
+        }
+        if self.prompt.is_some() {
+            self.handle_prompt_key(key);
+            return;
+        }
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Char('a') => self.start_prompt(Prompt::AddDescription, String::new()),
+            KeyCode::Char('c') | KeyCode::Char(' ') => self.toggle_complete(),
+            KeyCode::Char('s') => self.snooze(Duration::days(1)),
+            KeyCode::Char('S') => self.snooze(Duration::weeks(1)),
+            KeyCode::Char('d') => self.delete(),
+            KeyCode::Char('e') => {
+                if let Some(index) = self.selected_index() {
+                    let target = self.entries[index].clone();
+                    let description = target.task_description.clone();
+                    self.start_prompt(Prompt::EditDescription { target }, description);
+                }
+            },
+            KeyCode::Char('/') => {
+                let filter = self.filter.clone();
+                self.start_prompt(Prompt::Filter, filter);
+            },
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.clamp_selection();
+                self.message = HELP.to_string();
+            },
+            _ => {},
+        }
+    }
+
+    fn handle_prompt_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => {
+                self.prompt = None;
+                self.message = HELP.to_string();
+            },
+            KeyCode::Backspace => {
+                self.input.pop();
+            },
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Enter => {
+                if let Some(prompt) = self.prompt.take() {
+                    let input = std::mem::take(&mut self.input);
+                    self.submit(prompt, input.trim().to_string());
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn submit(&mut self, prompt: Prompt, input: String) {
+        let due_is_valid = NaiveDateTime::parse_from_str(&input, DATE_FORMAT).is_ok();
+        match prompt {
+            Prompt::AddDescription if input.is_empty() => self.message = HELP.to_string(),
+            Prompt::AddDescription => self.start_prompt(Prompt::AddDue { description: input }, String::new()),
+            Prompt::AddDue { description } if due_is_valid => {
+                self.reload();
+                let entry = TodoEntry {
+                    create_date: Local::now().format(DATE_FORMAT).to_string(),
+                    task_description: description,
+                    due_date: input,
+                    ..Default::default()
+                };
+                if let Some(problem) = entry.row_problem() {
+                    self.message = format!("Can't add the task: {}", problem);
+                    return;
+                }
+                self.message = format!("Added: {}", entry.task_description);
+                self.entries.push(entry.clone());
+                self.save("add", Some(entry));
+            },
+            Prompt::EditDescription { target } => {
+                let description = if input.is_empty() { target.task_description.clone() } else { input };
+                let due = target.due_date.clone();
+                self.start_prompt(Prompt::EditDue { target, description }, due);
+            },
+            Prompt::EditDue { target, description } if due_is_valid => {
+                let Some(index) = self.find_fresh(&target) else { return };
+                let changed = TodoEntry { task_description: description, due_date: input, ..self.entries[index].clone() };
+                if let Some(problem) = changed.row_problem() {
+                    self.message = format!("Can't update the task: {}", problem);
+                    return;
+                }
+                self.entries[index] = changed.clone();
+                self.message = format!("Updated: {}", changed.task_description);
+                self.save("edit", Some(changed));
+            },
+            Prompt::AddDue { .. } | Prompt::EditDue { .. } => {
+                self.message = format!("Not a YYYYMMDDHHMM date: {}", input);
+                self.prompt = Some(prompt);
+                self.input = input;
+            },
+            Prompt::Filter => {
+                self.filter = input;
+                self.list_state.select(Some(0));
+                self.clamp_selection();
+                self.message = HELP.to_string();
+            },
+        }
+    }
+}
+
+fn entry_style(entry: &TodoEntry, now: NaiveDateTime) -> Style {
+    if entry.completed.is_some() {
+        return Style::default().fg(Color::DarkGray).add_modifier(Modifier::CROSSED_OUT);
+    }
+    match entry.due() {
+        Some(due) if due < now => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        Some(due) if due < now + Duration::hours(DUE_SOON_HOURS) => Style::default().fg(Color::Yellow),
+        _ => Style::default(),
+    }
+}
+
+fn entry_line(entry: &TodoEntry) -> String {
+    let check = if entry.completed.is_some() { "[x]" } else { "[ ]" };
+    let due = entry
+        .due()
+        .map(|due| due.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "no due date     ".to_string());
+    let priority = entry.priority.map(|p| format!("({}) ", p)).unwrap_or_default();
+    let tags = entry.tags.iter().map(|tag| format!(" +{}", tag)).collect::<String>();
+    format!("{} {}  {}{}{}", check, due, priority, entry.task_description, tags)
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let [list_area, status_area] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+
+    let now = Local::now().naive_local();
+    let items: Vec<ListItem> = app
+        .visible()
+        .into_iter()
+        .map(|index| {
+            let entry = &app.entries[index];
+            ListItem::new(entry_line(entry)).style(entry_style(entry, now))
+        })
+        .collect();
+    let mut title = format!(" simple_todo: {} ", app.filename);
+    if !app.filter.is_empty() {
+        title.push_str(&format!("[filter: {}] ", app.filter));
+    }
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, list_area, &mut app.list_state);
+
+    let status = match &app.prompt {
+        Some(prompt) => Line::from(vec![
+            Span::styled(format!("{}: ", prompt.label()), Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(app.input.clone()),
+            Span::styled(format!("  {}", app.message), Style::default().fg(Color::Red)),
+        ]),
+        None => Line::from(app.message.clone()),
+    };
+    frame.render_widget(Paragraph::new(status), status_area);
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> io::Result<()> {
+    while !app.quit {
+        terminal.draw(|frame| draw(frame, app))?;
+        if !event::poll(REFRESH_INTERVAL)? {
+            app.refresh();
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                app.handle_key(key);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn run(filename: String) {
+    let mut app = App::new(filename);
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+    if let Err(error) = result {
+        println!("Error running the terminal UI: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A todo file of its own for each test, starting with `rows`.
+    fn todo_file(name: &str, rows: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("simple_todo-tui-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("todo");
+        fs::write(&path, rows).unwrap();
+        fs::remove_file(PathBuf::from(format!("{}.log", path.display()))).ok();
+        path.to_string_lossy().to_string()
+    }
+
+    fn press(app: &mut App, code: KeyCode) {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        text.chars().for_each(|c| press(app, KeyCode::Char(c)));
+    }
+
+    fn descriptions(filename: &str) -> Vec<String> {
+        read_todos(filename).into_iter().map(|entry| entry.task_description).collect()
+    }
+
+    #[test]
+    fn ctrl_c_quits_without_touching_the_list() {
+        let filename = todo_file("ctrl-c", "202401010000|>Walk|>202401020000");
+        let mut app = App::new(filename.clone());
+        press(&mut app, KeyCode::Char('a'));
+        app.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert!(app.quit);
+        assert_eq!(app.input, "");
+        assert_eq!(read_todos(&filename)[0].completed, None);
+    }
+
+    #[test]
+    fn keeps_tasks_written_elsewhere_while_open() {
+        let filename = todo_file("elsewhere", "202401010000|>Walk|>202401020000");
+        let mut app = App::new(filename.clone());
+        fs::write(&filename, "202401010000|>Walk|>202401020000\n202401010000|>Shop|>202401030000").unwrap();
+        press(&mut app, KeyCode::Char('c'));
+        assert_eq!(descriptions(&filename), vec!["Walk", "Shop"]);
+        assert!(read_todos(&filename)[0].completed.is_some());
+
+        press(&mut app, KeyCode::Char('a'));
+        type_text(&mut app, "Cook");
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "202401040000");
+        fs::write(&filename, "202401010000|>Walk|>202401020000").unwrap();
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(descriptions(&filename), vec!["Walk", "Cook"]);
+    }
+
+    #[test]
+    fn refuses_descriptions_that_would_break_the_row() {
+        let filename = todo_file("row", "202401010000|>Walk|>202401020000");
+        let mut app = App::new(filename.clone());
+        press(&mut app, KeyCode::Char('a'));
+        type_text(&mut app, "a |> b");
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "202401040000");
+        press(&mut app, KeyCode::Enter);
+        assert!(app.message.starts_with("Can't add the task"));
+        assert_eq!(descriptions(&filename), vec!["Walk"]);
+
+        press(&mut app, KeyCode::Char('e'));
+        type_text(&mut app, "Walk |> run");
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Enter);
+        assert!(app.message.starts_with("Can't update the task"));
+        assert_eq!(descriptions(&filename), vec!["Walk"]);
+        assert_eq!(app.entries[0].task_description, "Walk");
+    }
+
+    #[test]
+    fn leaves_a_task_changed_elsewhere_alone() {
+        let filename = todo_file("changed", "202401010000|>Walk|>202401020000");
+        let mut app = App::new(filename.clone());
+        fs::write(&filename, "202401010000|>Walk the dog|>202401020000").unwrap();
+        press(&mut app, KeyCode::Char('d'));
+        assert_eq!(descriptions(&filename), vec!["Walk the dog"]);
+        assert!(app.message.contains("Changed elsewhere"));
+    }
+
+    #[test]
+    fn escape_while_editing_the_due_date_discards_the_edit() {
+        let filename = todo_file("escape", "202401010000|>Walk|>202401020000");
+        let mut app = App::new(filename.clone());
+        press(&mut app, KeyCode::Char('e'));
+        press(&mut app, KeyCode::Backspace);
+        type_text(&mut app, "ks");
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Char('s'));
+        assert_eq!(descriptions(&filename), vec!["Walk"]);
+
+        press(&mut app, KeyCode::Char('e'));
+        type_text(&mut app, "ing");
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(descriptions(&filename), vec!["Walking"]);
+    }
+}
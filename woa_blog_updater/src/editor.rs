@@ -0,0 +1,116 @@
+//! Writing and editing posts in `$VISUAL`/`$EDITOR` instead of on one line
+//! of stdin.
+//!
+//! The post is shown as its title, an `=` underline, then the paragraphs
+//! separated by blank lines. Lines within a paragraph are joined back up with
+//! spaces, so hard-wrapping in the editor doesn't leak into the JSON. HTML
+//! posts keep their line breaks, and a blank line inside a `<pre>` doesn't
+//! end the block, so code keeps its layout.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+use crate::{BlogError, BlogResult, ContentFormat, Post};
+
+/// The editor to use, if the user has set one.
+pub fn editor_command() -> Option<String> {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|editor| !editor.trim().is_empty())
+}
+
+pub fn post_to_text(title: &str, content: &[String]) -> String {
+    let underline = "=".repeat(title.chars().count().max(3));
+    let mut text = format!("{}\n{}\n", title, underline);
+    for paragraph in content {
+        text.push('\n');
+        text.push_str(paragraph);
+        text.push('\n');
+    }
+    text
+}
+
+/// Whether `block` opens more `<pre>` elements than it closes.
+fn in_pre(block: &[&str]) -> bool {
+    let count = |tag: &str| block.iter().map(|line| line.matches(tag).count()).sum::<usize>();
+    count("<pre") > count("</pre>")
+}
+
+/// Reads a post back out of the editor; `None` if the title was left empty.
+pub fn post_from_text(text: &str, format: ContentFormat) -> Option<Post> {
+    let mut lines = text.lines().skip_while(|line| line.trim().is_empty()).peekable();
+    let title = lines.next()?.trim().to_string();
+    if title.is_empty() {
+        return None;
+    }
+    if lines.peek().is_some_and(|line| !line.trim().is_empty() && line.trim().chars().all(|c| c == '=')) {
+        lines.next();
+    }
+    let html = format == ContentFormat::Html;
+    let join = |paragraph: &[&str]| if html { paragraph.join("\n") } else { paragraph.join(" ") };
+    let mut content: Vec<String> = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    for line in lines {
+        if line.trim().is_empty() {
+            if html && in_pre(&paragraph) {
+                paragraph.push("");
+            } else if !paragraph.is_empty() {
+                content.push(join(&paragraph));
+                paragraph.clear();
+            }
+        } else if html {
+            paragraph.push(line.trim_end());
+        } else {
+            paragraph.push(line.trim());
+        }
+    }
+    if !paragraph.is_empty() {
+        content.push(join(&paragraph));
+    }
+    Some(Post { title, content })
+}
+
+/// Opens `initial` in the user's editor and returns what they saved.
+pub fn edit_text(editor: &str, initial: &str) -> BlogResult<String> {
+    let path = env::temp_dir().join(format!("woa-post-{}.txt", std::process::id()));
+    fs::write(&path, initial).map_err(|error| BlogError::EditorFailed(error.to_string()))?;
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status()
+        .map_err(|error| BlogError::EditorFailed(format!("{}: {}", program, error)))?;
+    let edited = fs::read_to_string(&path).map_err(|error| BlogError::EditorFailed(error.to_string()));
+    let _ = fs::remove_file(&path);
+    if !status.success() {
+        return Err(BlogError::EditorFailed(format!("{} exited with {}", program, status)));
+    }
+    edited
+}
+
+/// Edits a post in the editor, giving `None` if the user cleared the title.
+pub fn edit_post(editor: &str, title: &str, content: &[String], format: ContentFormat) -> BlogResult<Option<Post>> {
+    let text = edit_text(editor, &post_to_text(title, content))?;
+    Ok(post_from_text(&text, format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_wrapped_text_but_keeps_html_lines() {
+        let text = "Title\n=====\n\nOne\n  wrapped.\n\nTwo.\n";
+        assert_eq!(post_from_text(text, ContentFormat::Text).unwrap().content, vec!["One wrapped.", "Two."]);
+
+        let content = vec![
+            "<p>Code:</p>".to_string(),
+            "<pre><code>fn main() {\n    one();\n\n    two();\n}\n</code></pre>".to_string(),
+        ];
+        let text = post_to_text("Title", &content);
+        assert_eq!(post_from_text(&text, ContentFormat::Html).unwrap().content, content);
+    }
+}
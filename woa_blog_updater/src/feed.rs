@@ -0,0 +1,228 @@
+//! RSS 2.0 and Atom feeds for the published posts.
+//!
+//! Output depends only on the posts file: the feed's own timestamp is the
+//! newest post's time rather than the time of the build, and entries are
+//! identified by their permalinks, so regenerating an unchanged blog gives
+//! byte-identical feeds and readers don't see phantom updates. For the same
+//! reason post times are read in the page's `utc_offset` (UTC by default)
+//! rather than the zone of whoever runs the build.
+
+use std::fs;
+use std::path::Path;
+
+use chrono::prelude::*;
+
+use crate::site::{escape_html, render_content, POSTS_DIR};
+use crate::{BlogError, BlogPostsForJson, BlogResult, PostForJson, WoaTime};
+
+pub const RSS_FILE: &str = "rss.xml";
+pub const ATOM_FILE: &str = "atom.xml";
+
+/// Where the site is published and who writes it; flags override the page settings.
+pub struct FeedSettings {
+    pub site_url: String,
+    /// Falls back to the blog's title, as Atom needs an author for every entry.
+    pub author: String,
+    /// The offset post times are written in.
+    pub offset: FixedOffset,
+}
+
+impl FeedSettings {
+    /// `None` if the site's address isn't known.
+    pub fn resolve(
+        blog: &BlogPostsForJson,
+        site_url: Option<String>,
+        author: Option<String>,
+    ) -> BlogResult<Option<FeedSettings>> {
+        let offset = match &blog.page.utc_offset {
+            Some(offset) => offset.parse().map_err(|_| {
+                BlogError::PostsFileUnParsable(format!("page utc_offset '{}' should look like +01:00", offset))
+            })?,
+            None => FixedOffset::east_opt(0).expect("zero is a valid offset"),
+        };
+        let Some(site_url) = site_url.or_else(|| blog.page.site_url.clone()) else {
+            return Ok(None);
+        };
+        let author = author
+            .or_else(|| blog.page.author.clone())
+            .filter(|author| !author.trim().is_empty())
+            .unwrap_or_else(|| blog.page.title.clone());
+        Ok(Some(FeedSettings { site_url: site_url.trim_end_matches('/').to_string(), author, offset }))
+    }
+
+    fn permalink(&self, post: &PostForJson) -> String {
+        format!("{}/{}/{}/", self.site_url, POSTS_DIR, post.id)
+    }
+}
+
+/// The post's time in the configured offset; woa times have no zone.
+fn post_time(post: &PostForJson, settings: &FeedSettings) -> Option<DateTime<FixedOffset>> {
+    post.woa_time.in_zone(&settings.offset)
+}
+
+fn feed_posts<'a>(
+    blog: &'a BlogPostsForJson,
+    settings: &FeedSettings,
+) -> Vec<(&'a PostForJson, DateTime<FixedOffset>)> {
+    let now = WoaTime::now();
+    let mut posts: Vec<_> = blog
+        .posts
+        .iter()
+        .filter(|post| post.is_published(&now))
+        .filter_map(|post| post_time(post, settings).map(|time| (post, time)))
+        .collect();
+    posts.sort_by(|(a, a_time), (b, b_time)| b_time.cmp(a_time).then_with(|| a.id.cmp(&b.id)));
+    posts
+}
+
+pub fn rss(blog: &BlogPostsForJson, settings: &FeedSettings) -> String {
+    let posts = feed_posts(blog, settings);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n");
+    xml.push_str("  <channel>\n");
+    xml.push_str(&format!("    <title>{}</title>\n", escape_html(&blog.page.title)));
+    xml.push_str(&format!("    <link>{}/</link>\n", escape_html(&settings.site_url)));
+    xml.push_str(&format!("    <description>{}</description>\n", escape_html(&blog.page.title)));
+    xml.push_str(&format!(
+        "    <atom:link href=\"{}/{}\" rel=\"self\" type=\"application/rss+xml\"/>\n",
+        escape_html(&settings.site_url),
+        RSS_FILE
+    ));
+    if let Some((_, newest)) = posts.first() {
+        xml.push_str(&format!("    <lastBuildDate>{}</lastBuildDate>\n", newest.to_rfc2822()));
+    }
+    for (post, time) in &posts {
+        let link = escape_html(&settings.permalink(post));
+        xml.push_str("    <item>\n");
+        xml.push_str(&format!("      <title>{}</title>\n", escape_html(&post.title)));
+        xml.push_str(&format!("      <link>{}</link>\n", link));
+        xml.push_str(&format!("      <guid isPermaLink=\"true\">{}</guid>\n", link));
+        xml.push_str(&format!("      <pubDate>{}</pubDate>\n", time.to_rfc2822()));
+        xml.push_str(&format!("      <dc:creator>{}</dc:creator>\n", escape_html(&settings.author)));
+        for tag in &post.tags {
+            xml.push_str(&format!("      <category>{}</category>\n", escape_html(tag)));
+        }
+        xml.push_str(&format!("      <description>{}</description>\n", escape_html(render_content(post).trim())));
+        xml.push_str("    </item>\n");
+    }
+    xml.push_str("  </channel>\n</rss>\n");
+    xml
+}
+
+pub fn atom(blog: &BlogPostsForJson, settings: &FeedSettings) -> String {
+    let posts = feed_posts(blog, settings);
+    let epoch = DateTime::<Utc>::UNIX_EPOCH.fixed_offset();
+    let updated = posts.first().map(|(_, time)| *time).unwrap_or(epoch);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <title>{}</title>\n", escape_html(&blog.page.title)));
+    xml.push_str(&format!("  <id>{}/</id>\n", escape_html(&settings.site_url)));
+    xml.push_str(&format!("  <link href=\"{}/\"/>\n", escape_html(&settings.site_url)));
+    xml.push_str(&format!("  <link href=\"{}/{}\" rel=\"self\"/>\n", escape_html(&settings.site_url), ATOM_FILE));
+    xml.push_str(&format!("  <updated>{}</updated>\n", updated.to_rfc3339()));
+    xml.push_str(&format!("  <author><name>{}</name></author>\n", escape_html(&settings.author)));
+    for (post, time) in &posts {
+        let link = escape_html(&settings.permalink(post));
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", escape_html(&post.title)));
+        xml.push_str(&format!("    <id>{}</id>\n", link));
+        xml.push_str(&format!("    <link href=\"{}\"/>\n", link));
+        xml.push_str(&format!("    <published>{}</published>\n", time.to_rfc3339()));
+        xml.push_str(&format!("    <updated>{}</updated>\n", time.to_rfc3339()));
+        for tag in &post.tags {
+            xml.push_str(&format!("    <category term=\"{}\"/>\n", escape_html(tag)));
+        }
+        xml.push_str(&format!("    <content type=\"html\">{}</content>\n", escape_html(render_content(post).trim())));
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// Writes both feeds into `out_dir`, leaving files whose contents haven't
+/// changed untouched so their modification times stay put too.
+pub fn write_feeds(blog: &BlogPostsForJson, settings: &FeedSettings, out_dir: &Path) -> BlogResult<()> {
+    fs::create_dir_all(out_dir)
+        .map_err(|error| BlogError::CouldNotWriteSite(format!("{}: {}", out_dir.display(), error)))?;
+    for (name, contents) in [(RSS_FILE, rss(blog, settings)), (ATOM_FILE, atom(blog, settings))] {
+        let path = out_dir.join(name);
+        if fs::read_to_string(&path).is_ok_and(|existing| existing == contents) {
+            continue;
+        }
+        fs::write(&path, contents)
+            .map_err(|error| BlogError::CouldNotWriteSite(format!("{}: {}", path.display(), error)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ContentFormat;
+
+    fn blog(utc_offset: Option<&str>) -> BlogPostsForJson {
+        let mut blog = BlogPostsForJson::default();
+        blog.page.title = "WoA Blog".to_string();
+        blog.page.site_url = Some("https://blog.example.com/".to_string());
+        blog.page.utc_offset = utc_offset.map(str::to_string);
+        blog.posts.push(PostForJson {
+            id: "hello".to_string(),
+            woa_time: "2024:05:01:09:30:00".parse().unwrap(),
+            title: "Hello".to_string(),
+            content: vec!["Hi.".to_string()],
+            content_format: ContentFormat::Text,
+            tags: Vec::new(),
+            draft: false,
+        });
+        blog
+    }
+
+    #[test]
+    fn times_use_the_configured_offset_and_author_falls_back_to_the_title() {
+        let settings = FeedSettings::resolve(&blog(None), None, None).unwrap().unwrap();
+        let atom = atom(&blog(None), &settings);
+        assert!(atom.contains("<published>2024-05-01T09:30:00+00:00</published>"));
+        assert!(atom.contains("<author><name>WoA Blog</name></author>"));
+
+        let blog = blog(Some("+02:00"));
+        let settings = FeedSettings::resolve(&blog, None, Some("Azi".to_string())).unwrap().unwrap();
+        let rss = rss(&blog, &settings);
+        assert!(rss.contains("<pubDate>Wed, 1 May 2024 09:30:00 +0200</pubDate>"));
+        assert!(rss.contains("<dc:creator>Azi</dc:creator>"));
+    }
+
+    #[test]
+    fn rejects_an_offset_that_is_not_fixed() {
+        assert!(FeedSettings::resolve(&blog(Some("Europe/Paris")), None, None).is_err());
+    }
+
+    #[test]
+    fn building_twice_gives_the_same_bytes() {
+        let mut blog = blog(None);
+        blog.posts.insert(0, PostForJson {
+            id: "newer".to_string(),
+            woa_time: "2024:06:02:18:00:00".parse().unwrap(),
+            title: "Newer".to_string(),
+            content: vec!["Again.".to_string()],
+            content_format: ContentFormat::Text,
+            tags: vec!["rust".to_string()],
+            draft: false,
+        });
+        let out = std::env::temp_dir().join(format!("woa-feed-twice-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&out);
+
+        let mut builds = Vec::new();
+        for _ in 0..2 {
+            let settings = FeedSettings::resolve(&blog, None, None).unwrap().unwrap();
+            write_feeds(&blog, &settings, &out).unwrap();
+            builds.push((fs::read(out.join(RSS_FILE)).unwrap(), fs::read(out.join(ATOM_FILE)).unwrap()));
+        }
+        assert_eq!(builds[0], builds[1]);
+
+        let (rss, atom) = &builds[0];
+        let (rss, atom) = (String::from_utf8_lossy(rss), String::from_utf8_lossy(atom));
+        assert!(rss.contains("<lastBuildDate>Sun, 2 Jun 2024 18:00:00 +0000</lastBuildDate>"));
+        assert!(atom.contains("  <updated>2024-06-02T18:00:00+00:00</updated>\n  <author>"));
+        fs::remove_dir_all(&out).unwrap();
+    }
+}
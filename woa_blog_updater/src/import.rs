@@ -0,0 +1,433 @@
+//! `import`: brings posts in from other blogs' exports.
+//!
+//! * A WordPress export (WXR, the `.xml` from Tools → Export). Posts keep
+//!   their `wp:post_date`, tags and categories; unpublished posts become
+//!   drafts and pages, attachments and trashed posts are left out.
+//! * A directory of Jekyll or Hugo Markdown posts, read the same way as
+//!   `add-post --file`. A post without a date in its front matter takes it
+//!   from a Jekyll-style `YYYY-MM-DD-` file name.
+//! * A JSON Feed (<https://jsonfeed.org>), dated by `date_published`.
+//!
+//! Every post keeps its original time. Posts with the same title and day as
+//! one already in the blog, or earlier in the same import, are skipped.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::Deserialize;
+
+use crate::{markdown, BlogError, BlogPostsForJson, BlogResult, ContentFormat, PostForJson, WoaTime};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ImportFormat {
+    Wordpress,
+    Markdown,
+    JsonFeed,
+}
+
+impl ImportFormat {
+    /// Guesses the format from the source: a directory is Markdown, otherwise
+    /// the file's extension decides.
+    pub fn detect(source: &Path) -> Option<ImportFormat> {
+        if source.is_dir() {
+            return Some(ImportFormat::Markdown);
+        }
+        match source.extension()?.to_str()?.to_lowercase().as_str() {
+            "xml" | "wxr" => Some(ImportFormat::Wordpress),
+            "json" => Some(ImportFormat::JsonFeed),
+            _ => None,
+        }
+    }
+}
+
+/// What a source held: the posts that could be converted, and why the rest
+/// couldn't.
+#[derive(Default)]
+pub struct Imported {
+    pub posts: Vec<PostForJson>,
+    pub skipped: Vec<String>,
+}
+
+fn import_error(source: &Path, reason: impl std::fmt::Display) -> BlogError {
+    BlogError::ImportFailed(format!("{}: {}", source.display(), reason))
+}
+
+pub fn read_source(source: &Path, format: ImportFormat) -> BlogResult<Imported> {
+    match format {
+        ImportFormat::Markdown => read_markdown_dir(source),
+        ImportFormat::Wordpress | ImportFormat::JsonFeed => {
+            let text = fs::read_to_string(source).map_err(|error| import_error(source, error))?;
+            match format {
+                ImportFormat::Wordpress => read_wordpress(&text).map_err(|error| import_error(source, error)),
+                _ => read_json_feed(&text).map_err(|error| import_error(source, error)),
+            }
+        },
+    }
+}
+
+fn dedupe_key(post: &PostForJson) -> (String, NaiveDate) {
+    let title = post.title.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    (title, post.woa_time.date())
+}
+
+/// Adds the posts that aren't already in the blog and returns the ids of the
+/// new ones along with the titles of those skipped as duplicates.
+pub fn merge(blog: &mut BlogPostsForJson, posts: Vec<PostForJson>) -> (Vec<String>, Vec<String>) {
+    let mut seen: HashSet<(String, NaiveDate)> = blog.posts.iter().map(dedupe_key).collect();
+    let mut added = Vec::new();
+    let mut duplicates = Vec::new();
+    for mut post in posts {
+        post.title = post.title.trim().to_string();
+        if seen.insert(dedupe_key(&post)) {
+            added.push(blog.insert_post(post));
+        } else {
+            duplicates.push(format!("{} ({})", post.title, post.woa_time.date()));
+        }
+    }
+    (added, duplicates)
+}
+
+/// Splits exported HTML into blocks the way `content` holds them. WordPress
+/// stores most paragraphs as bare text between blank lines and only adds the
+/// `<p>` tags when it displays them, so those are wrapped here.
+fn html_blocks(html: &str) -> Vec<String> {
+    const BLOCK_TAGS: &[&str] = &[
+        "<p", "<div", "<h1", "<h2", "<h3", "<h4", "<h5", "<h6", "<ul", "<ol", "<pre", "<blockquote", "<table", "<figure",
+        "<hr", "<!--",
+    ];
+    let mut blocks = Vec::new();
+    let mut current = String::new();
+    for chunk in html.replace("\r\n", "\n").split("\n\n") {
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(chunk);
+        // A blank line inside <pre> is part of the block, not the end of it.
+        if current.matches("<pre").count() > current.matches("</pre>").count() {
+            continue;
+        }
+        let block = current.trim();
+        if !block.is_empty() {
+            let lower = block.to_lowercase();
+            if BLOCK_TAGS.iter().any(|tag| lower.starts_with(tag)) {
+                blocks.push(block.to_string());
+            } else {
+                blocks.push(format!("<p>{}</p>", block.replace('\n', "<br>\n")));
+            }
+        }
+        current.clear();
+    }
+    if !current.trim().is_empty() {
+        blocks.push(current.trim().to_string());
+    }
+    blocks
+}
+
+fn text_paragraphs(text: &str) -> Vec<String> {
+    text.replace("\r\n", "\n")
+        .split("\n\n")
+        .map(|paragraph| paragraph.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect()
+}
+
+#[derive(Default)]
+struct WxrItem {
+    title: String,
+    content: String,
+    post_type: String,
+    status: String,
+    post_date: String,
+    post_date_gmt: String,
+    pub_date: String,
+    tags: Vec<String>,
+}
+
+impl WxrItem {
+    /// The blog's own wall-clock time if WordPress recorded one, falling back
+    /// to the GMT and RSS dates, which drafts sometimes have instead.
+    fn time(&self) -> Option<WoaTime> {
+        const WXR_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+        if let Ok(naive) = NaiveDateTime::parse_from_str(self.post_date.trim(), WXR_FORMAT) {
+            return Some(WoaTime::from_datetime(&Local.from_local_datetime(&naive).earliest()?));
+        }
+        if let Ok(naive) = NaiveDateTime::parse_from_str(self.post_date_gmt.trim(), WXR_FORMAT) {
+            return Some(WoaTime::from_datetime(&Utc.from_utc_datetime(&naive).with_timezone(&Local)));
+        }
+        DateTime::parse_from_rfc2822(self.pub_date.trim())
+            .ok()
+            .map(|time| WoaTime::from_datetime(&time.with_timezone(&Local)))
+    }
+
+    fn into_post(self) -> Result<Option<PostForJson>, String> {
+        if self.post_type != "post" || matches!(self.status.as_str(), "trash" | "auto-draft" | "inherit") {
+            return Ok(None);
+        }
+        let title = self.title.trim().to_string();
+        if title.is_empty() {
+            return Err("a post with no title".to_string());
+        }
+        let woa_time = self.time().ok_or_else(|| format!("'{}' has no usable date", title))?;
+        let mut post = PostForJson {
+            id: String::new(),
+            woa_time,
+            title,
+            content: html_blocks(&self.content),
+            content_format: ContentFormat::Html,
+            tags: Vec::new(),
+            draft: !matches!(self.status.as_str(), "publish" | "future"),
+        };
+        post.add_tags(&self.tags);
+        Ok(Some(post))
+    }
+}
+
+fn read_wordpress(text: &str) -> Result<Imported, String> {
+    let mut imported = Imported::default();
+    let mut reader = Reader::from_str(text);
+    let mut item: Option<WxrItem> = None;
+    let mut value = String::new();
+    let mut category_domain = String::new();
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|error| format!("invalid XML at byte {}: {}", reader.error_position(), error))?;
+        match event {
+            Event::Start(start) => {
+                value.clear();
+                if start.name().as_ref() == b"item" {
+                    item = Some(WxrItem::default());
+                } else if start.name().as_ref() == b"category" {
+                    category_domain = start
+                        .try_get_attribute("domain")
+                        .ok()
+                        .flatten()
+                        .map(|domain| String::from_utf8_lossy(&domain.value).to_string())
+                        .unwrap_or_default();
+                }
+            },
+            Event::Text(text) => {
+                value.push_str(&text.unescape().map_err(|error| error.to_string())?);
+            },
+            Event::CData(data) => value.push_str(&String::from_utf8_lossy(&data.into_inner())),
+            Event::End(end) => {
+                let name = String::from_utf8_lossy(end.name().as_ref()).to_string();
+                if name == "item" {
+                    match item.take().map(WxrItem::into_post) {
+                        Some(Ok(Some(post))) => imported.posts.push(post),
+                        Some(Err(reason)) => imported.skipped.push(reason),
+                        _ => {},
+                    }
+                } else if let Some(item) = item.as_mut() {
+                    let value = std::mem::take(&mut value);
+                    match name.as_str() {
+                        "title" => item.title = value,
+                        "content:encoded" => item.content = value,
+                        "wp:post_type" => item.post_type = value.trim().to_string(),
+                        "wp:status" => item.status = value.trim().to_string(),
+                        "wp:post_date" => item.post_date = value,
+                        "wp:post_date_gmt" => item.post_date_gmt = value,
+                        "pubDate" => item.pub_date = value,
+                        "category"
+                            if matches!(category_domain.as_str(), "post_tag" | "category")
+                                && value.trim() != "Uncategorized" =>
+                        {
+                            item.tags.push(value.trim().to_string());
+                        },
+                        _ => {},
+                    }
+                }
+            },
+            Event::Eof => break,
+            _ => {},
+        }
+    }
+    Ok(imported)
+}
+
+#[derive(Deserialize)]
+struct JsonFeed {
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Deserialize)]
+struct JsonFeedItem {
+    id: Option<serde_json::Value>,
+    title: Option<String>,
+    content_html: Option<String>,
+    content_text: Option<String>,
+    date_published: Option<String>,
+    date_modified: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+fn read_json_feed(text: &str) -> Result<Imported, String> {
+    let feed: JsonFeed = serde_json::from_str(text)
+        .map_err(|error| format!("not a JSON Feed (line {}, column {}): {}", error.line(), error.column(), error))?;
+    let mut imported = Imported::default();
+    for (index, item) in feed.items.into_iter().enumerate() {
+        let name = match &item.id {
+            Some(serde_json::Value::String(id)) => id.clone(),
+            Some(id) => id.to_string(),
+            None => format!("item {}", index + 1),
+        };
+        let Some(title) = item.title.map(|title| title.trim().to_string()).filter(|title| !title.is_empty()) else {
+            imported.skipped.push(format!("{} has no title", name));
+            continue;
+        };
+        let date = item.date_published.or(item.date_modified).unwrap_or_default();
+        let Ok(woa_time) = WoaTime::from_iso(&date) else {
+            imported.skipped.push(format!("'{}' has no usable date", title));
+            continue;
+        };
+        let (content, content_format) = match (item.content_html, item.content_text) {
+            (Some(html), _) => (html_blocks(&html), ContentFormat::Html),
+            (None, Some(text)) => (text_paragraphs(&text), ContentFormat::Text),
+            (None, None) => (Vec::new(), ContentFormat::Text),
+        };
+        let mut post =
+            PostForJson { id: String::new(), woa_time, title, content, content_format, tags: Vec::new(), draft: false };
+        post.add_tags(&item.tags);
+        imported.posts.push(post);
+    }
+    Ok(imported)
+}
+
+/// The date at the start of a Jekyll post's file name, `2024-05-01-title.md`.
+fn date_from_file_name(path: &Path) -> Option<WoaTime> {
+    let name = path.file_name()?.to_str()?;
+    let day = NaiveDate::parse_from_str(name.get(..10)?, "%Y-%m-%d").ok()?;
+    Local.from_local_datetime(&day.and_hms_opt(0, 0, 0)?).earliest().map(|time| WoaTime::from_datetime(&time))
+}
+
+fn markdown_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            markdown_files(&path, files)?;
+        } else if path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| matches!(extension, "md" | "markdown"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn read_markdown_dir(dir: &Path) -> BlogResult<Imported> {
+    let mut files = Vec::new();
+    markdown_files(dir, &mut files).map_err(|error| import_error(dir, error))?;
+    files.sort();
+    let mut imported = Imported::default();
+    for file in files {
+        match markdown::post_from_file(&file, date_from_file_name(&file)) {
+            Ok(post) => imported.posts.push(post),
+            Err(error) => imported.skipped.push(error.to_string()),
+        }
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::post;
+
+    const WXR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/" xmlns:wp="http://wordpress.org/export/1.2/">
+<channel>
+  <title>Old blog</title>
+  <item>
+    <title>Hello &amp; welcome</title>
+    <content:encoded><![CDATA[First paragraph.
+
+<h2>A heading</h2>
+
+Second one.]]></content:encoded>
+    <wp:post_date><![CDATA[2015-03-04 10:22:33]]></wp:post_date>
+    <wp:status><![CDATA[publish]]></wp:status>
+    <wp:post_type><![CDATA[post]]></wp:post_type>
+    <category domain="category" nicename="uncategorized"><![CDATA[Uncategorized]]></category>
+    <category domain="post_tag" nicename="rust"><![CDATA[rust]]></category>
+  </item>
+  <item>
+    <title>About</title>
+    <wp:post_date>2015-01-01 00:00:00</wp:post_date>
+    <wp:status>publish</wp:status>
+    <wp:post_type>page</wp:post_type>
+  </item>
+  <item>
+    <title>Unfinished</title>
+    <wp:post_date>2016-01-01 00:00:00</wp:post_date>
+    <wp:status>draft</wp:status>
+    <wp:post_type>post</wp:post_type>
+  </item>
+</channel>
+</rss>"#;
+
+    #[test]
+    fn reads_wordpress_posts_with_their_dates() {
+        let imported = read_wordpress(WXR).unwrap();
+        assert!(imported.skipped.is_empty());
+        assert_eq!(imported.posts.len(), 2);
+        let post = &imported.posts[0];
+        assert_eq!(post.title, "Hello & welcome");
+        assert_eq!(post.woa_time.to_string(), "2015:03:04:10:22:33");
+        assert_eq!(post.content, vec!["<p>First paragraph.</p>", "<h2>A heading</h2>", "<p>Second one.</p>"]);
+        assert_eq!(post.tags, vec!["rust"]);
+        assert!(!post.draft);
+        assert!(imported.posts[1].draft);
+    }
+
+    #[test]
+    fn reads_json_feeds_and_skips_undated_items() {
+        let feed = r#"{"version": "https://jsonfeed.org/version/1.1", "items": [
+            {"id": "1", "title": "Dated", "content_text": "One.\n\nTwo.", "date_published": "2019-07-01T12:00:00Z"},
+            {"id": "2", "title": "Undated", "content_html": "<p>x</p>"}
+        ]}"#;
+        let imported = read_json_feed(feed).unwrap();
+        assert_eq!(imported.posts.len(), 1);
+        assert_eq!(imported.posts[0].content, vec!["One.", "Two."]);
+        assert_eq!(imported.skipped, vec!["'Undated' has no usable date"]);
+    }
+
+    #[test]
+    fn skips_posts_with_the_same_title_and_day() {
+        let mut blog = BlogPostsForJson::default();
+        merge(&mut blog, vec![post("Hello", "2015:03:04:10:22:33")]);
+        let (added, duplicates) = merge(&mut blog, vec![
+            post("hello ", "2015:03:04:18:00:00"),
+            post("Hello", "2015:03:05:10:00:00"),
+        ]);
+        assert_eq!(added, vec!["2015-03-05-hello"]);
+        assert_eq!(duplicates, vec!["hello (2015-03-04)"]);
+    }
+
+    #[test]
+    fn imported_posts_never_take_an_existing_posts_id() {
+        let mut blog = BlogPostsForJson::default();
+        merge(&mut blog, vec![post("Hello?", "2015:03:04:10:00:00")]);
+        let (added, duplicates) = merge(&mut blog, vec![post("Hello!", "2015:03:04:18:00:00")]);
+        assert_eq!(added, vec!["2015-03-04-hello-2"]);
+        assert!(duplicates.is_empty());
+        assert_eq!(blog.post("2015-03-04-hello").unwrap().title, "Hello?");
+    }
+
+    #[test]
+    fn dates_jekyll_posts_from_their_file_names() {
+        let time = date_from_file_name(Path::new("_posts/2014-12-25-christmas.md")).unwrap();
+        assert_eq!(time.to_string(), "2014:12:25:00:00:00");
+        assert!(date_from_file_name(Path::new("content/posts/christmas.md")).is_none());
+    }
+}
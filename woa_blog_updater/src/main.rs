@@ -1,41 +1,250 @@
+use std::collections::HashSet;
 use std::env;
 use std::io::stdin;
+use std::path::{Path, PathBuf};
 
 use chrono::prelude::*;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 
+mod editor;
+mod feed;
+mod import;
+mod markdown;
+mod serve;
+mod site;
+mod storage;
+mod validate;
+mod woa_time;
+
+use woa_time::WoaTime;
+
 const POSTS_FILE_VAR_NAME: &str = "BLOG_POSTS_FILE";
 
 type BlogResult<T> = std::result::Result<T, BlogError>;
 
 #[derive(Debug)]
 enum BlogError {
-    UnableToReadPostsFile,
-    PostsFileUnParsable,
-    CouldNotWritePostsFile,
+    UnableToReadPostsFile(String),
+    PostsFileUnParsable(String),
+    CouldNotWritePostsFile(String),
+    UnableToReadTemplate(String),
+    CouldNotWriteSite(String),
+    PostNotFound(String),
+    EditorFailed(String),
+    UnableToReadMarkdown(String),
+    InvalidFrontMatter(String),
+    InvalidTime(String),
+    InvalidWoaTime(String),
+    InvalidIsoTime(String),
+    NonexistentTime(String),
+    ServerFailed(String),
+    ImportFailed(String),
+}
+
+impl std::fmt::Display for BlogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BlogError::UnableToReadPostsFile(e) => write!(f, "Unable to read blog posts file {}", e),
+            BlogError::PostsFileUnParsable(e) => write!(f, "Blog posts file is not valid: {}", e),
+            BlogError::CouldNotWritePostsFile(e) => write!(f, "Could not write blog posts file {}", e),
+            BlogError::UnableToReadTemplate(e) => write!(f, "Unable to read template {}", e),
+            BlogError::CouldNotWriteSite(e) => write!(f, "Could not write site file {}", e),
+            BlogError::PostNotFound(id) => write!(f, "No post with id '{}' (see `list`)", id),
+            BlogError::EditorFailed(e) => write!(f, "Editing failed: {}", e),
+            BlogError::UnableToReadMarkdown(e) => write!(f, "Unable to read markdown file {}", e),
+            BlogError::InvalidFrontMatter(e) => write!(f, "Invalid front matter in {}", e),
+            BlogError::InvalidTime(e) => write!(f, "Unrecognised time '{}', use YYYY-MM-DD [HH:MM]", e),
+            BlogError::InvalidWoaTime(e) => write!(f, "Invalid woa time '{}', expected YYYY:MM:DD:HH:MM:SS", e),
+            BlogError::InvalidIsoTime(e) => write!(f, "Unrecognised ISO 8601 time '{}'", e),
+            BlogError::ImportFailed(e) => write!(f, "Could not import {}", e),
+            BlogError::ServerFailed(e) => write!(f, "Could not start the preview server on {}", e),
+            BlogError::NonexistentTime(e) => {
+                write!(f, "{} falls in a daylight saving gap and doesn't exist in the local time zone", e)
+            },
+        }
+    }
+}
+
+impl std::error::Error for BlogError {}
+
+/// Prints the error and exits, for commands that can't carry on without the result.
+fn or_exit<T>(result: BlogResult<T>) -> T {
+    result.unwrap_or_else(|error| {
+        println!("{}", error);
+        std::process::exit(1);
+    })
 }
 
-#[derive(clap::ValueEnum, Clone, Debug)]
+#[derive(Subcommand, Clone, Debug)]
 enum Command {
-    AddPost,
+    /// Write a new post, in $VISUAL/$EDITOR if one is set, or add one from a Markdown file
+    AddPost {
+        /// Markdown file with YAML (---) or TOML (+++) front matter
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        #[command(flatten)]
+        publish: PublishArgs,
+    },
     ListPosts,
+    /// List post ids, times and titles, optionally filtered
+    List {
+        /// Only posts whose title contains this (case-insensitive)
+        #[arg(short, long)]
+        title: Option<String>,
+        /// Only posts from this day on (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<NaiveDate>,
+        /// Only posts up to and including this day (YYYY-MM-DD)
+        #[arg(long)]
+        until: Option<NaiveDate>,
+        /// Only posts with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Include drafts
+        #[arg(long)]
+        drafts: bool,
+        /// Show at most this many posts
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+    /// Publish a draft now, or schedule it with --at
+    Publish {
+        id: String,
+        /// Publish time (YYYY-MM-DD [HH:MM]); a future time schedules the post
+        #[arg(long)]
+        at: Option<String>,
+    },
+    /// Turn a post back into a draft
+    Unpublish { id: String },
+    /// Add tags to a post, or remove them with --remove
+    Tag {
+        id: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+        #[arg(short, long)]
+        remove: bool,
+    },
+    /// Print one post in full
+    ShowPost { id: String },
+    /// Change a post's title and body in $VISUAL/$EDITOR
+    EditPost { id: String },
+    /// Remove a post
+    DeletePost {
+        id: String,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Move a post to a new position in the list (1 is the top)
+    MovePost { id: String, position: usize },
+    /// Render the posts into a static HTML site
+    Build {
+        /// Directory to write the site into
+        #[arg(short, long, default_value = "site")]
+        out: PathBuf,
+        /// Directory of templates overriding the built-in ones
+        #[arg(short, long)]
+        templates: Option<PathBuf>,
+        #[command(flatten)]
+        feed: FeedArgs,
+    },
+    /// Import posts from a WordPress export, a directory of Jekyll/Hugo posts or a JSON Feed
+    Import {
+        /// WordPress export (.xml), Markdown directory or JSON Feed (.json)
+        source: PathBuf,
+        /// Source format, if it can't be told from the source
+        #[arg(short, long, value_enum)]
+        format: Option<import::ImportFormat>,
+        /// List what would be imported without saving it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Preview the site on localhost, rebuilding and reloading it on every change
+    Serve {
+        #[arg(short, long, default_value_t = 8000)]
+        port: u16,
+        /// Directory of templates overriding the built-in ones
+        #[arg(short, long)]
+        templates: Option<PathBuf>,
+    },
+    /// Check the posts file for problems without changing it
+    Validate,
+    /// Write RSS 2.0 (rss.xml) and Atom (atom.xml) feeds of the posts
+    Feed {
+        /// Directory to write the feeds into
+        #[arg(short, long, default_value = "site")]
+        out: PathBuf,
+        #[command(flatten)]
+        feed: FeedArgs,
+    },
+}
+
+// The commands that work on the posts file, and those that don't need one.
+#[derive(Subcommand, Clone, Debug)]
+enum TopCommand {
+    #[command(flatten)]
+    Posts(Command),
+    /// Convert between woa times (YYYY:MM:DD:HH:MM:SS) and ISO 8601
+    WoaTime {
+        #[command(subcommand)]
+        action: woa_time::WoaTimeCommand,
+    },
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct PublishArgs {
+    /// Keep the post as an unpublished draft
+    #[arg(long)]
+    draft: bool,
+    /// Publish time (YYYY-MM-DD [HH:MM]); a future time schedules the post
+    #[arg(long)]
+    at: Option<String>,
+    /// Tag the post; can be given more than once
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+}
+
+impl PublishArgs {
+    fn apply(&self, post: &mut PostForJson) -> BlogResult<()> {
+        if self.draft {
+            post.draft = true;
+        }
+        if let Some(at) = &self.at {
+            post.woa_time = parse_publish_time(at)?;
+        }
+        post.add_tags(&self.tags);
+        Ok(())
+    }
+}
+
+fn parse_publish_time(text: &str) -> BlogResult<WoaTime> {
+    markdown::parse_date(text)
+        .map(|time| WoaTime::from_datetime(&time))
+        .ok_or_else(|| BlogError::InvalidTime(text.to_string()))
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct FeedArgs {
+    /// Public address of the site (defaults to the page's site_url)
+    #[arg(long)]
+    site_url: Option<String>,
+    /// Feed author (defaults to the page's author)
+    #[arg(long)]
+    author: Option<String>,
 }
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    command: Command,
+    #[command(subcommand)]
+    command: TopCommand,
 }
 
 #[derive(Debug)]
 struct Post {
     title: String,
-    body: String,
-}
-
-fn time_to_soleilfou(time: DateTime<Local>) -> String {
-    time.format("%Y:%m:%d:%H:%M:%S").to_string()
+    content: Vec<String>,
 }
 
 fn split_content(content: &str) -> Vec<String> {
@@ -44,12 +253,14 @@ fn split_content(content: &str) -> Vec<String> {
 
 impl Post {
     fn for_json(&self) -> PostForJson {
-        let sf_time = time_to_soleilfou(Local::now());
-        let content = split_content(&self.body);
         PostForJson {
-            woa_time: sf_time,
+            id: String::new(),
+            woa_time: WoaTime::now(),
             title: self.title.clone(),
-            content,
+            content: self.content.clone(),
+            content_format: ContentFormat::Text,
+            tags: Vec::new(),
+            draft: false,
         }
     }
 }
@@ -67,100 +278,545 @@ fn create_post() -> Post {
     stdin().read_line(&mut body).expect("Unable to read body");
     Post {
         title: title.trim().to_string(),
-        body: body.trim().to_string(),
+        content: split_content(body.trim()),
     }
 }
 
+fn write_post() -> BlogResult<Option<Post>> {
+    match editor::editor_command() {
+        Some(editor) => editor::edit_post(&editor, "", &[], ContentFormat::Text),
+        None => Ok(Some(create_post())),
+    }
+}
+
+fn confirm(question: &str) -> bool {
+    println!("{} (y/n)", question);
+    let mut response = String::new();
+    stdin().read_line(&mut response).expect("Unable to read response");
+    response.trim() == "y"
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct PostForJson {
-    woa_time: String,
+    /// Stable slug used for permalinks and by the edit/delete commands.
+    #[serde(default)]
+    id: String,
+    woa_time: WoaTime,
     title: String,
     content: Vec<String>,
+    /// Whether `content` holds plain-text paragraphs or rendered HTML blocks.
+    #[serde(default, skip_serializing_if = "ContentFormat::is_text")]
+    content_format: ContentFormat,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    draft: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ContentFormat {
+    #[default]
+    Text,
+    Html,
+}
+
+impl ContentFormat {
+    fn is_text(&self) -> bool {
+        *self == ContentFormat::Text
+    }
+}
+
+impl PostForJson {
+    fn default_id(&self) -> String {
+        site::slugify(&format!("{} {}", self.woa_time.date(), self.title))
+    }
+
+    /// Drafts are never published; other posts appear once their time has come,
+    /// which is how scheduled posts wait for the first build after their date.
+    fn is_published(&self, now: &WoaTime) -> bool {
+        !self.draft && self.woa_time <= *now
+    }
+
+    fn is_scheduled(&self, now: &WoaTime) -> bool {
+        !self.draft && self.woa_time > *now
+    }
+
+    fn status(&self, now: &WoaTime) -> &'static str {
+        if self.draft {
+            "  (draft)"
+        } else if self.is_scheduled(now) {
+            "  (scheduled)"
+        } else {
+            ""
+        }
+    }
+
+    fn add_tags(&mut self, tags: &[String]) {
+        for tag in tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+            if !self.tags.iter().any(|existing| existing == tag) {
+                self.tags.push(tag.to_string());
+            }
+        }
+    }
+
+    fn print(&self) {
+        println!("Title: {}", self.title);
+        println!("WoA Time: {}", self.woa_time);
+        if !self.tags.is_empty() {
+            println!("Tags: {}", self.tags.join(", "));
+        }
+        if self.draft {
+            println!("(draft)");
+        }
+        for content in &self.content {
+            println!("{}", content);
+        }
+        println!("-----------------");
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
 struct FooterLink {
     label: String,
     url: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 struct Page {
     title: String,
     css: String,
+    /// Public address of the site, used for feed links.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    site_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    /// Offset the woa times are written in, such as `+01:00`; feeds use UTC without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    utc_offset: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 struct BlogPostsForJson {
     page: Page,
     footer_links: Vec<FooterLink>,
+    /// In the file, only published posts: the blog frontend reads this array.
+    /// Once loaded, every post.
     posts: Vec<PostForJson>,
+    /// Drafts and scheduled posts, kept out of `posts` in the file. They are
+    /// merged into `posts` on load and split back out on save.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unpublished: Vec<PostForJson>,
+}
+
+/// The posts file as written: published posts apart from the rest.
+#[derive(Serialize)]
+struct BlogFile<'a> {
+    page: &'a Page,
+    footer_links: &'a [FooterLink],
+    posts: Vec<&'a PostForJson>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unpublished: Vec<&'a PostForJson>,
 }
 
 impl BlogPostsForJson {
-    fn from_json_string(json_string: &str) -> BlogResult<BlogPostsForJson> {
-        serde_json::from_str(json_string).map_err(|_| BlogError::PostsFileUnParsable)
+    fn from_json_string(filename: &str, json_string: &str) -> BlogResult<BlogPostsForJson> {
+        let mut blog = storage::parse(Path::new(filename), json_string)?;
+        for post in std::mem::take(&mut blog.unpublished) {
+            let index = blog.insert_position(&post);
+            blog.posts.insert(index, post);
+        }
+        blog.ensure_ids();
+        Ok(blog)
+    }
+
+    /// Gives every post without an id (or with a clashing one) a unique slug,
+    /// so files written before ids existed pick them up on the next save.
+    /// Every id already given out is kept, so a new post whose slug clashes
+    /// with an older one gets the suffix and no permalink changes.
+    fn ensure_ids(&mut self) {
+        let mut taken: HashSet<String> = HashSet::new();
+        let needs_id: Vec<bool> =
+            self.posts.iter().map(|post| post.id.is_empty() || !taken.insert(post.id.clone())).collect();
+        for (post, needs_id) in self.posts.iter_mut().zip(needs_id) {
+            if !needs_id {
+                continue;
+            }
+            let base = match post.default_id() {
+                id if id.is_empty() => "post".to_string(),
+                id => id,
+            };
+            let mut id = base.clone();
+            let mut suffix = 2;
+            while taken.contains(&id) {
+                id = format!("{}-{}", base, suffix);
+                suffix += 1;
+            }
+            taken.insert(id.clone());
+            post.id = id;
+        }
+    }
+
+    fn position_of(&self, id: &str) -> BlogResult<usize> {
+        self.posts
+            .iter()
+            .position(|post| post.id == id)
+            .ok_or_else(|| BlogError::PostNotFound(id.to_string()))
+    }
+
+    fn post(&self, id: &str) -> BlogResult<&PostForJson> {
+        self.position_of(id).map(|index| &self.posts[index])
     }
 
     fn to_json_string(&self) -> BlogResult<String> {
-        serde_json::to_string(self).map_err(|_| BlogError::CouldNotWritePostsFile)
+        let now = WoaTime::now();
+        let (posts, unpublished) = self.posts.iter().partition(|post| post.is_published(&now));
+        let file = BlogFile { page: &self.page, footer_links: &self.footer_links, posts, unpublished };
+        serde_json::to_string_pretty(&file).map_err(|error| BlogError::CouldNotWritePostsFile(error.to_string()))
     }
 
     fn save_to_file(&self, filename: &str) -> BlogResult<()> {
         let json_string = self.to_json_string()?;
-        std::fs::write(filename, json_string).map_err(|_| BlogError::CouldNotWritePostsFile)
+        storage::save(Path::new(filename), &json_string)
     }
 
     fn from_file(filename: &str) -> BlogResult<BlogPostsForJson> {
-        let file_contents =
-            std::fs::read_to_string(filename).map_err(|_| BlogError::UnableToReadPostsFile)?;
-        BlogPostsForJson::from_json_string(&file_contents)
+        match storage::read(Path::new(filename))? {
+            Some(file_contents) => BlogPostsForJson::from_json_string(filename, &file_contents),
+            None => Err(BlogError::UnableToReadPostsFile(format!("{}: no such file", filename))),
+        }
     }
 
-    fn add_post(&mut self, post: Post) {
-        self.posts.insert(0, post.for_json());
+    /// Like `from_file`, but a missing file is a new, empty blog. A file that
+    /// exists and doesn't parse is still an error, never an empty blog.
+    fn from_file_or_new(filename: &str) -> BlogResult<BlogPostsForJson> {
+        match storage::read(Path::new(filename))? {
+            Some(file_contents) => BlogPostsForJson::from_json_string(filename, &file_contents),
+            None => Ok(BlogPostsForJson::default()),
+        }
+    }
+
+    /// Where `post` goes among the others by time, newest first: just above
+    /// the first post from the top that isn't newer. Posts put out of order
+    /// with `move_post` stay where they are, and new posts settle next to them
+    /// rather than the list being re-sorted.
+    fn insert_position(&self, post: &PostForJson) -> usize {
+        self.posts.iter().position(|existing| existing.woa_time <= post.woa_time).unwrap_or(self.posts.len())
+    }
+
+    /// Inserts a post at its [`insert_position`](Self::insert_position) and returns its id.
+    fn insert_post(&mut self, post: PostForJson) -> String {
+        let index = self.insert_position(&post);
+        self.posts.insert(index, post);
+        self.ensure_ids();
+        self.posts[index].id.clone()
+    }
+
+    fn edit_post(&mut self, id: &str, post: Post) -> BlogResult<()> {
+        let index = self.position_of(id)?;
+        self.posts[index].title = post.title;
+        self.posts[index].content = post.content;
+        Ok(())
+    }
+
+    fn post_mut(&mut self, id: &str) -> BlogResult<&mut PostForJson> {
+        let index = self.position_of(id)?;
+        Ok(&mut self.posts[index])
+    }
+
+    /// Re-files a post among the others after its time changed.
+    fn reposition(&mut self, id: &str) -> BlogResult<()> {
+        let post = self.delete_post(id)?;
+        self.insert_post(post);
+        Ok(())
+    }
+
+    fn delete_post(&mut self, id: &str) -> BlogResult<PostForJson> {
+        let index = self.position_of(id)?;
+        Ok(self.posts.remove(index))
+    }
+
+    /// Moves a post to `position`, counting from 1 at the top of the list.
+    /// Drafts and scheduled posts are saved apart and filed by time again
+    /// when the file is loaded, so only the order of published posts lasts.
+    fn move_post(&mut self, id: &str, position: usize) -> BlogResult<()> {
+        let index = self.position_of(id)?;
+        let post = self.posts.remove(index);
+        let target = position.saturating_sub(1).min(self.posts.len());
+        self.posts.insert(target, post);
+        Ok(())
     }
 }
 
 fn main() {
-    let args = Args::parse();
-    let filename = env::var(POSTS_FILE_VAR_NAME).expect("No blog posts file specified");
-    match args.command {
-        Command::AddPost => {
-            let mut blog_posts =
-                BlogPostsForJson::from_file(&filename).unwrap_or_else(|_| BlogPostsForJson {
-                    posts: vec![],
-                    footer_links: vec![],
-                    page: Page {
-                        title: "".to_string(),
-                        css: "".to_string(),
-                    },
-                });
-            let new_post = create_post();
-            blog_posts.add_post(new_post);
-            blog_posts
-                .save_to_file(&filename)
-                .expect("Unable to save blog posts");
+    match Args::parse().command {
+        TopCommand::Posts(command) => {
+            run(command, &env::var(POSTS_FILE_VAR_NAME).expect("No blog posts file specified"))
+        },
+        TopCommand::WoaTime { action } => println!("{}", or_exit(woa_time::run(action))),
+    }
+}
+
+fn run(command: Command, filename: &str) {
+    match command {
+        Command::AddPost { file: Some(file), publish } => {
+            let mut blog_posts = or_exit(BlogPostsForJson::from_file_or_new(filename));
+            let mut post = or_exit(markdown::post_from_file(&file, Some(WoaTime::now())));
+            or_exit(publish.apply(&mut post));
+            let id = blog_posts.insert_post(post);
+            or_exit(blog_posts.save_to_file(filename));
+            println!("Added post {} from {}", id, file.display());
+        }
+        Command::AddPost { file: None, publish } => {
+            let mut blog_posts = or_exit(BlogPostsForJson::from_file_or_new(filename));
+            let Some(new_post) = or_exit(write_post()) else {
+                println!("Post not saved: it has no title");
+                return;
+            };
+            let mut post = new_post.for_json();
+            or_exit(publish.apply(&mut post));
+            let id = blog_posts.insert_post(post);
+            or_exit(blog_posts.save_to_file(filename));
+            println!("Added post {}", id);
         }
         Command::ListPosts => {
-            println!("BlogPosts in file: {}", &filename);
-            let blog_posts =
-                BlogPostsForJson::from_file(&filename).unwrap_or_else(|_| BlogPostsForJson {
-                    posts: vec![],
-                    footer_links: vec![],
-                    page: Page {
-                        title: "".to_string(),
-                        css: "".to_string(),
-                    },
-                });
-            for post in blog_posts.posts {
-                println!("Title: {}", post.title);
-                println!("WoA Time: {}", post.woa_time);
-                for content in post.content {
-                    println!("{}", content);
+            println!("BlogPosts in file: {}", filename);
+            let blog_posts = or_exit(BlogPostsForJson::from_file_or_new(filename));
+            for post in blog_posts.posts.iter().filter(|post| !post.draft) {
+                post.print();
+            }
+        }
+        Command::List { title, since, until, tag, drafts, limit } => {
+            let blog_posts = or_exit(BlogPostsForJson::from_file(filename));
+            let now = WoaTime::now();
+            let title = title.map(|title| title.to_lowercase());
+            let matching = blog_posts
+                .posts
+                .iter()
+                .filter(|post| drafts || !post.draft)
+                .filter(|post| tag.as_ref().is_none_or(|tag| post.tags.contains(tag)))
+                .filter(|post| title.as_ref().is_none_or(|title| post.title.to_lowercase().contains(title)))
+                .filter(|post| since.is_none_or(|since| post.woa_time.date() >= since))
+                .filter(|post| until.is_none_or(|until| post.woa_time.date() <= until))
+                .take(limit.unwrap_or(usize::MAX));
+            for post in matching {
+                println!("{}  {}  {}{}", post.woa_time, post.id, post.title, post.status(&now));
+            }
+        }
+        Command::Publish { id, at } => {
+            let mut blog_posts = or_exit(BlogPostsForJson::from_file(filename));
+            let woa_time = match at {
+                Some(at) => or_exit(parse_publish_time(&at)),
+                None => WoaTime::now(),
+            };
+            let post = or_exit(blog_posts.post_mut(&id));
+            post.draft = false;
+            post.woa_time = woa_time;
+            or_exit(blog_posts.reposition(&id));
+            or_exit(blog_posts.save_to_file(filename));
+            if woa_time > WoaTime::now() {
+                println!("Scheduled post {} for {}", id, woa_time);
+            } else {
+                println!("Published post {}", id);
+            }
+        }
+        Command::Unpublish { id } => {
+            let mut blog_posts = or_exit(BlogPostsForJson::from_file(filename));
+            or_exit(blog_posts.post_mut(&id)).draft = true;
+            or_exit(blog_posts.save_to_file(filename));
+            println!("Post {} is a draft again", id);
+        }
+        Command::Tag { id, tags, remove } => {
+            let mut blog_posts = or_exit(BlogPostsForJson::from_file(filename));
+            let post = or_exit(blog_posts.post_mut(&id));
+            if remove {
+                post.tags.retain(|tag| !tags.contains(tag));
+            } else {
+                post.add_tags(&tags);
+            }
+            println!("Post {} tags: {}", id, post.tags.join(", "));
+            or_exit(blog_posts.save_to_file(filename));
+        }
+        Command::ShowPost { id } => {
+            let blog_posts = or_exit(BlogPostsForJson::from_file(filename));
+            or_exit(blog_posts.post(&id)).print();
+        }
+        Command::EditPost { id } => {
+            let mut blog_posts = or_exit(BlogPostsForJson::from_file(filename));
+            let Some(editor) = editor::editor_command() else {
+                println!("Set VISUAL or EDITOR to edit posts");
+                std::process::exit(1);
+            };
+            let post = or_exit(blog_posts.post(&id));
+            match or_exit(editor::edit_post(&editor, &post.title, &post.content, post.content_format)) {
+                None => println!("Post not changed: the title was left empty"),
+                Some(edited) => {
+                    or_exit(blog_posts.edit_post(&id, edited));
+                    or_exit(blog_posts.save_to_file(filename));
+                    println!("Updated post {}", id);
                 }
-                println!("-----------------");
             }
         }
+        Command::DeletePost { id, yes } => {
+            let mut blog_posts = or_exit(BlogPostsForJson::from_file(filename));
+            let title = or_exit(blog_posts.post(&id)).title.clone();
+            if !yes && !confirm(&format!("Delete '{}'?", title)) {
+                return;
+            }
+            or_exit(blog_posts.delete_post(&id));
+            or_exit(blog_posts.save_to_file(filename));
+            println!("Deleted post {}", id);
+        }
+        Command::MovePost { id, position } => {
+            let mut blog_posts = or_exit(BlogPostsForJson::from_file(filename));
+            or_exit(blog_posts.move_post(&id, position));
+            or_exit(blog_posts.save_to_file(filename));
+            println!("Moved post {} to position {}", id, position.max(1));
+        }
+        Command::Build { out, templates, feed } => {
+            let blog_posts = or_exit(BlogPostsForJson::from_file(filename));
+            let feeds = or_exit(feed::FeedSettings::resolve(&blog_posts, feed.site_url, feed.author));
+            let count = or_exit(site::build(
+                &blog_posts,
+                Path::new(filename),
+                &out,
+                templates.as_deref(),
+                feeds.as_ref(),
+            ));
+            // Moves scheduled posts whose time has come into `posts` for the frontend.
+            or_exit(blog_posts.save_to_file(filename));
+            println!("Built {} post(s) into {}", count, out.display());
+            if feeds.is_none() {
+                println!("No feeds written: set the page's site_url or pass --site-url");
+            }
+        }
+        Command::Import { source, format, dry_run } => {
+            let Some(format) = format.or_else(|| import::ImportFormat::detect(&source)) else {
+                println!("Can't tell what {} is, pass --format", source.display());
+                std::process::exit(1);
+            };
+            let mut blog_posts = or_exit(BlogPostsForJson::from_file_or_new(filename));
+            let imported = or_exit(import::read_source(&source, format));
+            for reason in &imported.skipped {
+                println!("Skipped {}", reason);
+            }
+            let (added, duplicates) = import::merge(&mut blog_posts, imported.posts);
+            for duplicate in &duplicates {
+                println!("Already in the blog: {}", duplicate);
+            }
+            let now = WoaTime::now();
+            for id in &added {
+                let post = or_exit(blog_posts.post(id));
+                println!("{}  {}  {}{}", post.woa_time, post.id, post.title, post.status(&now));
+            }
+            if dry_run {
+                println!("Would import {} post(s) (dry run, nothing saved)", added.len());
+            } else {
+                or_exit(blog_posts.save_to_file(filename));
+                println!("Imported {} post(s), skipped {} duplicate(s)", added.len(), duplicates.len());
+            }
+        }
+        Command::Serve { port, templates } => {
+            or_exit(serve::serve(Path::new(filename), templates.as_deref(), port));
+        }
+        Command::Validate => {
+            let path = Path::new(filename);
+            let Some(text) = or_exit(storage::read(path)) else {
+                println!("{} does not exist yet", filename);
+                std::process::exit(1);
+            };
+            let (blog, raw) = or_exit(validate::parse(path, &text));
+            let problems = validate::check(&blog, &raw);
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            let errors = problems.iter().filter(|problem| problem.severity == validate::Severity::Error).count();
+            println!(
+                "{}: {} post(s), {} error(s), {} warning(s)",
+                filename,
+                blog.post_count(),
+                errors,
+                problems.len() - errors
+            );
+            if errors > 0 {
+                std::process::exit(1);
+            }
+        }
+        Command::Feed { out, feed } => {
+            let blog_posts = or_exit(BlogPostsForJson::from_file(filename));
+            let Some(settings) = or_exit(feed::FeedSettings::resolve(&blog_posts, feed.site_url, feed.author)) else {
+                println!("Feeds need the site's address: set the page's site_url or pass --site-url");
+                std::process::exit(1);
+            };
+            or_exit(feed::write_feeds(&blog_posts, &settings, &out));
+            println!("Wrote {} and {} into {}", feed::RSS_FILE, feed::ATOM_FILE, out.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A published plain-text post with no content or tags.
+    pub(crate) fn post(title: &str, time: &str) -> PostForJson {
+        PostForJson {
+            id: String::new(),
+            woa_time: time.parse().unwrap(),
+            title: title.to_string(),
+            content: Vec::new(),
+            content_format: ContentFormat::Text,
+            tags: Vec::new(),
+            draft: false,
+        }
+    }
+
+    #[test]
+    fn a_new_post_never_takes_an_existing_posts_id() {
+        let mut blog = BlogPostsForJson::default();
+        assert_eq!(blog.insert_post(post("Hello?", "2024:05:01:09:00:00")), "2024-05-01-hello");
+        assert_eq!(blog.insert_post(post("Hello!", "2024:05:01:18:00:00")), "2024-05-01-hello-2");
+        assert_eq!(blog.insert_post(post("Hello.", "2024:05:01:12:00:00")), "2024-05-01-hello-3");
+        let ids: Vec<&str> = blog.posts.iter().map(|post| post.id.as_str()).collect();
+        assert_eq!(ids, vec!["2024-05-01-hello-2", "2024-05-01-hello-3", "2024-05-01-hello"]);
+    }
+
+    #[test]
+    fn unpublished_posts_are_saved_apart_from_posts() {
+        let mut blog = BlogPostsForJson::default();
+        blog.insert_post(post("Old", "2024:05:01:09:00:00"));
+        blog.insert_post(PostForJson { draft: true, ..post("Draft", "2024:05:02:09:00:00") });
+        blog.insert_post(post("Later", "9999:01:01:00:00:00"));
+
+        let json = blog.to_json_string().unwrap();
+        let raw: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let titles = |key: &str| -> Vec<String> {
+            raw[key].as_array().unwrap().iter().map(|post| post["title"].as_str().unwrap().to_string()).collect()
+        };
+        assert_eq!(titles("posts"), vec!["Old"]);
+        assert_eq!(titles("unpublished"), vec!["Later", "Draft"]);
+
+        let loaded = BlogPostsForJson::from_json_string("posts.json", &json).unwrap();
+        let titles: Vec<&str> = loaded.posts.iter().map(|post| post.title.as_str()).collect();
+        assert_eq!(titles, vec!["Later", "Draft", "Old"]);
+    }
+
+    #[test]
+    fn new_posts_settle_next_to_posts_moved_by_hand() {
+        let mut blog = BlogPostsForJson::default();
+        blog.insert_post(post("March", "2024:03:01:09:00:00"));
+        blog.insert_post(post("May", "2024:05:01:09:00:00"));
+        blog.insert_post(post("January", "2024:01:01:09:00:00"));
+        blog.move_post("2024-01-01-january", 1).unwrap();
+
+        blog.insert_post(post("April", "2024:04:01:09:00:00"));
+        blog.insert_post(post("February", "2024:02:01:09:00:00"));
+        blog.insert_post(PostForJson { draft: true, ..post("Draft", "2024:02:15:09:00:00") });
+        let titles = |blog: &BlogPostsForJson| blog.posts.iter().map(|post| post.title.clone()).collect::<Vec<_>>();
+        assert_eq!(titles(&blog), vec!["April", "Draft", "February", "January", "May", "March"]);
+
+        blog.move_post("2024-02-15-draft", 1).unwrap();
+        let loaded = BlogPostsForJson::from_json_string("posts.json", &blog.to_json_string().unwrap()).unwrap();
+        assert_eq!(titles(&loaded), vec!["April", "Draft", "February", "January", "May", "March"]);
     }
 }
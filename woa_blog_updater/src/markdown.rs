@@ -0,0 +1,239 @@
+//! Posts written as Markdown files with YAML (`---`) or TOML (`+++`) front
+//! matter:
+//!
+//! ```markdown
+//! ---
+//! title: Shipping it
+//! date: 2024-05-01 09:30
+//! tags: [rust, release]
+//! draft: false
+//! ---
+//! The body, in **Markdown**.
+//! ```
+//!
+//! The body is rendered to HTML one top-level block at a time, so `content`
+//! keeps one entry per paragraph, list, code block and so on, and the post is
+//! marked with `content_format: "html"`.
+
+use std::fs;
+use std::path::Path;
+
+use chrono::prelude::*;
+use pulldown_cmark::{html, Event, Options, Parser};
+use serde::Deserialize;
+
+use crate::{BlogError, BlogResult, ContentFormat, PostForJson, WoaTime};
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum DateValue {
+    Text(String),
+    Toml(toml::value::Datetime),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum TagsValue {
+    List(Vec<String>),
+    Text(String),
+}
+
+#[derive(Deserialize, Debug)]
+struct FrontMatter {
+    title: String,
+    date: Option<DateValue>,
+    tags: Option<TagsValue>,
+    /// Jekyll's categories, kept as tags.
+    categories: Option<TagsValue>,
+    #[serde(default)]
+    draft: bool,
+    /// Jekyll's way of marking a draft.
+    published: Option<bool>,
+}
+
+impl TagsValue {
+    fn into_tags(self) -> Vec<String> {
+        match self {
+            TagsValue::List(tags) => tags,
+            TagsValue::Text(tags) => tags
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|tag| !tag.is_empty())
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+/// Splits `---` YAML or `+++` TOML front matter off the top of a file.
+fn split_front_matter(text: &str) -> BlogResult<(FrontMatter, &str)> {
+    let text = text.trim_start_matches('\u{feff}');
+    let first_line = text.lines().next().unwrap_or("").trim_end();
+    if first_line != "---" && first_line != "+++" {
+        return Err(BlogError::InvalidFrontMatter("the file must start with --- or +++ front matter".to_string()));
+    }
+    let rest = &text[first_line.len()..].trim_start_matches(['\r', '\n']);
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == first_line {
+            let matter = &rest[..offset];
+            let body = &rest[offset + line.len()..];
+            let front_matter = if first_line == "---" {
+                serde_yaml::from_str(matter).map_err(|error| BlogError::InvalidFrontMatter(error.to_string()))?
+            } else {
+                toml::from_str(matter).map_err(|error| BlogError::InvalidFrontMatter(error.to_string()))?
+            };
+            return Ok((front_matter, body));
+        }
+        offset += line.len();
+    }
+    Err(BlogError::InvalidFrontMatter(format!("no closing {} after the front matter", first_line)))
+}
+
+/// Accepts the date shapes front matter tends to use, read as local time.
+pub fn parse_date(text: &str) -> Option<DateTime<Local>> {
+    let text = text.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Local));
+    }
+    if let Ok(time) = DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S %z") {
+        return Some(time.with_timezone(&Local));
+    }
+    let naive = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().and_then(|day| day.and_hms_opt(0, 0, 0)))?;
+    Local.from_local_datetime(&naive).earliest()
+}
+
+/// Renders Markdown to HTML, one string per top-level block.
+pub fn render_blocks(markdown: &str) -> Vec<String> {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_TASKLISTS);
+
+    let mut blocks = Vec::new();
+    let mut current: Vec<Event> = Vec::new();
+    let mut depth = 0;
+    for event in Parser::new_ext(markdown, options) {
+        match &event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => {},
+        }
+        current.push(event);
+        if depth == 0 {
+            let mut block = String::new();
+            html::push_html(&mut block, current.drain(..));
+            let block = block.trim().to_string();
+            if !block.is_empty() {
+                blocks.push(block);
+            }
+        }
+    }
+    blocks
+}
+
+/// Builds a post from Markdown text with front matter; posts without a date
+/// get `default_time`, and are refused if there is none.
+pub fn post_from_markdown(text: &str, default_time: Option<WoaTime>) -> BlogResult<PostForJson> {
+    let (front_matter, body) = split_front_matter(text)?;
+    let woa_time = match front_matter.date {
+        None => default_time.ok_or_else(|| BlogError::InvalidFrontMatter("there is no date".to_string()))?,
+        Some(date) => {
+            let date = match date {
+                DateValue::Text(text) => text,
+                DateValue::Toml(datetime) => datetime.to_string(),
+            };
+            let time =
+                parse_date(&date).ok_or_else(|| BlogError::InvalidFrontMatter(format!("unrecognised date '{}'", date)))?;
+            WoaTime::from_datetime(&time)
+        },
+    };
+    let mut post = PostForJson {
+        id: String::new(),
+        woa_time,
+        title: front_matter.title.trim().to_string(),
+        content: render_blocks(body),
+        content_format: ContentFormat::Html,
+        tags: Vec::new(),
+        draft: front_matter.draft || front_matter.published == Some(false),
+    };
+    post.add_tags(&front_matter.tags.map(TagsValue::into_tags).unwrap_or_default());
+    post.add_tags(&front_matter.categories.map(TagsValue::into_tags).unwrap_or_default());
+    Ok(post)
+}
+
+pub fn post_from_file(path: &Path, default_time: Option<WoaTime>) -> BlogResult<PostForJson> {
+    let text = fs::read_to_string(path)
+        .map_err(|error| BlogError::UnableToReadMarkdown(format!("{}: {}", path.display(), error)))?;
+    post_from_markdown(&text, default_time).map_err(|error| match error {
+        BlogError::InvalidFrontMatter(reason) => {
+            BlogError::InvalidFrontMatter(format!("{}: {}", path.display(), reason))
+        },
+        other => other,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn woa_time(text: &str) -> WoaTime {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn reads_yaml_and_toml_front_matter() {
+        let yaml = "---\ntitle: Shipping it\ndate: 2024-05-01 09:30\ntags: [rust, release]\n---\nThe **body**.\n";
+        let post = post_from_markdown(yaml, None).unwrap();
+        assert_eq!(post.title, "Shipping it");
+        assert_eq!(post.woa_time, woa_time("2024:05:01:09:30:00"));
+        assert_eq!(post.tags, ["rust", "release"]);
+        assert_eq!(post.content, ["<p>The <strong>body</strong>.</p>"]);
+        assert_eq!(post.content_format, ContentFormat::Html);
+        assert!(!post.draft);
+
+        let toml = "+++\ntitle = \"Shipping it\"\ndate = 2024-05-01T09:30:00\ntags = \"rust, release\"\n+++\nBody.\n";
+        let post = post_from_markdown(toml, None).unwrap();
+        assert_eq!(post.title, "Shipping it");
+        assert_eq!(post.woa_time, woa_time("2024:05:01:09:30:00"));
+        assert_eq!(post.tags, ["rust", "release"]);
+    }
+
+    #[test]
+    fn reads_jekyll_drafts_and_categories() {
+        let text = "---\ntitle: Old\npublished: false\ntags: rust\ncategories: [notes, rust]\n---\n";
+        let post = post_from_markdown(text, Some(woa_time("2015:03:04:00:00:00"))).unwrap();
+        assert!(post.draft);
+        assert_eq!(post.woa_time, woa_time("2015:03:04:00:00:00"));
+        assert_eq!(post.tags, ["rust", "notes"]);
+    }
+
+    #[test]
+    fn refuses_broken_front_matter() {
+        let unclosed = post_from_markdown("---\ntitle: Never closed\nBody.\n", None);
+        assert!(matches!(unclosed, Err(BlogError::InvalidFrontMatter(reason)) if reason.contains("no closing ---")));
+        assert!(post_from_markdown("title: No fences\n", None).is_err());
+        assert!(post_from_markdown("---\ntitle: Undated\n---\n", None).is_err());
+        assert!(post_from_markdown("---\ntitle: Bad date\ndate: 01/05/2024\n---\n", None).is_err());
+    }
+
+    #[test]
+    fn accepts_the_usual_date_shapes() {
+        let wall_clock = |text: &str| parse_date(text).map(|time| time.naive_local().to_string());
+        assert_eq!(wall_clock("2024-05-01").unwrap(), "2024-05-01 00:00:00");
+        assert_eq!(wall_clock("2024-05-01 09:30").unwrap(), "2024-05-01 09:30:00");
+        assert_eq!(wall_clock("2024-05-01T09:30").unwrap(), "2024-05-01 09:30:00");
+        assert_eq!(wall_clock("2024-05-01 09:30:15").unwrap(), "2024-05-01 09:30:15");
+        assert_eq!(wall_clock("2024-05-01T09:30:15").unwrap(), "2024-05-01 09:30:15");
+
+        let utc = |text: &str| parse_date(text).map(|time| time.with_timezone(&Utc).to_rfc3339());
+        assert_eq!(utc("2024-05-01T09:30:00+02:00").unwrap(), "2024-05-01T07:30:00+00:00");
+        assert_eq!(utc("2024-05-01 09:30:00 +0200").unwrap(), "2024-05-01T07:30:00+00:00");
+
+        assert_eq!(parse_date("01/05/2024"), None);
+        assert_eq!(parse_date("2024-05-01 9.30"), None);
+    }
+}
@@ -0,0 +1,243 @@
+//! `serve`: a preview of the site on localhost that reloads itself whenever
+//! the posts file, its stylesheet or a template changes.
+//!
+//! The site is built exactly as `build` would build it, into a fresh
+//! directory under `woa-serve-<port>` in the system temp dir each time, and
+//! only swapped in once the build has succeeded, so a half-saved posts file
+//! never takes the preview down. The build it replaced is kept until the next
+//! swap, for requests that were already reading from it. Pages poll
+//! `/__woa_reload` for the build number and reload when it moves on; the
+//! script is added when a page is served, never written into the built files.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::feed::FeedSettings;
+use crate::{site, BlogError, BlogPostsForJson, BlogResult};
+
+const RELOAD_PATH: &str = "/__woa_reload";
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const RELOAD_SCRIPT: &str = r#"<script>
+(function () {
+  var build = "{{build}}";
+  setInterval(function () {
+    fetch("/__woa_reload").then(function (response) { return response.text(); }).then(function (current) {
+      if (current !== build) { location.reload(); }
+    }).catch(function () {});
+  }, 1000);
+})();
+</script>
+"#;
+
+struct Preview {
+    posts_file: PathBuf,
+    templates: Option<PathBuf>,
+    site_url: String,
+    work_dir: PathBuf,
+    build: AtomicU64,
+    root: Mutex<Option<PathBuf>>,
+    /// The build before `root`, removed at the next swap.
+    retired: Mutex<Option<PathBuf>>,
+    /// The stylesheet the last build copied, if any, so watching doesn't
+    /// have to parse the posts file to find it.
+    stylesheet: Mutex<Option<PathBuf>>,
+}
+
+impl Preview {
+    /// Modification times of everything the site is built from.
+    fn watched(&self) -> Vec<(PathBuf, Option<SystemTime>)> {
+        let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        let mut paths = vec![self.posts_file.clone()];
+        paths.extend(self.stylesheet.lock().unwrap().clone());
+        if let Some(Ok(entries)) = self.templates.as_ref().map(fs::read_dir) {
+            let mut templates: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+            templates.sort();
+            paths.extend(templates);
+        }
+        paths.into_iter().map(|path| (path.clone(), modified(&path))).collect()
+    }
+
+    /// Builds into a new directory and swaps it in, leaving the previous
+    /// build up if this one fails.
+    fn rebuild(&self) -> BlogResult<usize> {
+        let blog = BlogPostsForJson::from_file(&self.posts_file.to_string_lossy())?;
+        let build = self.build.load(Ordering::SeqCst) + 1;
+        let out_dir = self.work_dir.join(build.to_string());
+        let feeds = FeedSettings::resolve(&blog, Some(self.site_url.clone()), None)?;
+        let count = site::build(&blog, &self.posts_file, &out_dir, self.templates.as_deref(), feeds.as_ref())?;
+        let posts_dir = self.posts_file.parent().unwrap_or_else(|| Path::new("."));
+        let css = posts_dir.join(blog.page.css.trim());
+        *self.stylesheet.lock().unwrap() = (!blog.page.css.trim().is_empty() && css.is_file()).then_some(css);
+
+        let previous = self.root.lock().unwrap().replace(out_dir);
+        self.build.store(build, Ordering::SeqCst);
+        if let Some(stale) = std::mem::replace(&mut *self.retired.lock().unwrap(), previous) {
+            let _ = fs::remove_dir_all(stale);
+        }
+        Ok(count)
+    }
+
+    fn watch(&self) {
+        let mut seen = self.watched();
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let current = self.watched();
+            if current == seen {
+                continue;
+            }
+            seen = current;
+            match self.rebuild() {
+                Ok(count) => println!("Rebuilt {} post(s)", count),
+                Err(error) => println!("Not rebuilt: {}", error),
+            }
+        }
+    }
+
+    fn respond(&self, request: Request) {
+        let path = request.url().split(['?', '#']).next().unwrap_or("/").to_string();
+        let build = self.build.load(Ordering::SeqCst).to_string();
+        let response = if path == RELOAD_PATH {
+            with_type(Response::from_string(build), "text/plain; charset=utf-8")
+        } else {
+            match self.file_for(&path) {
+                Some(file) => match fs::read(&file) {
+                    Ok(mut body) => {
+                        let content_type = content_type(&file);
+                        if content_type.starts_with("text/html") {
+                            body = inject_reload(&String::from_utf8_lossy(&body), &build).into_bytes();
+                        }
+                        with_type(Response::from_data(body), content_type)
+                    },
+                    Err(_) => not_found(),
+                },
+                None => not_found(),
+            }
+        };
+        if let Err(error) = request.respond(response) {
+            println!("Could not answer {}: {}", path, error);
+        }
+    }
+
+    /// The built file a URL path refers to, with directories served by their
+    /// `index.html`. Paths that would leave the site aren't served.
+    fn file_for(&self, url_path: &str) -> Option<PathBuf> {
+        let mut file = self.root.lock().unwrap().clone()?;
+        for segment in url_path.split('/').filter(|segment| !segment.is_empty()) {
+            let segment = percent_decode(segment);
+            if segment == ".." || segment.contains(['/', '\\']) {
+                return None;
+            }
+            file.push(segment);
+        }
+        if file.is_dir() {
+            file.push("index.html");
+        }
+        file.is_file().then_some(file)
+    }
+}
+
+fn with_type<R: std::io::Read>(response: Response<R>, content_type: &str) -> Response<R> {
+    let header = Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).expect("valid header");
+    response.with_header(header)
+}
+
+fn not_found() -> Response<std::io::Cursor<Vec<u8>>> {
+    with_type(Response::from_string("Not found").with_status_code(404), "text/plain; charset=utf-8")
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("xml") => "application/xml; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+fn inject_reload(html: &str, build: &str) -> String {
+    let script = RELOAD_SCRIPT.replace("{{build}}", build);
+    match html.rfind("</body>") {
+        Some(end) => format!("{}{}{}", &html[..end], script, &html[end..]),
+        None => format!("{}{}", html, script),
+    }
+}
+
+/// Decodes `%XX` escapes, so tag pages with non-ASCII slugs can be found.
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes.get(index + 1..index + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match (bytes[index], hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Serves the site on `127.0.0.1:port` until interrupted.
+pub fn serve(posts_file: &Path, templates: Option<&Path>, port: u16) -> BlogResult<()> {
+    let address = format!("127.0.0.1:{}", port);
+    let server = Server::http(&address).map_err(|error| BlogError::ServerFailed(format!("{}: {}", address, error)))?;
+    let preview = Arc::new(Preview {
+        posts_file: posts_file.to_path_buf(),
+        templates: templates.map(Path::to_path_buf),
+        site_url: format!("http://{}", address),
+        work_dir: std::env::temp_dir().join(format!("woa-serve-{}", port)),
+        build: AtomicU64::new(0),
+        root: Mutex::new(None),
+        retired: Mutex::new(None),
+        stylesheet: Mutex::new(None),
+    });
+    // Left over from a previous preview on this port that was interrupted.
+    let _ = fs::remove_dir_all(&preview.work_dir);
+    let count = preview.rebuild()?;
+    println!("Serving {} post(s) at http://{}/ (Ctrl-C to stop)", count, address);
+
+    let watcher = Arc::clone(&preview);
+    thread::spawn(move || watcher.watch());
+    for request in server.incoming_requests() {
+        preview.respond(request);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn injects_the_reload_script_before_the_body_ends() {
+        let html = inject_reload("<html><body><p>hi</p></body></html>", "7");
+        assert!(html.starts_with("<html><body><p>hi</p><script>"));
+        assert!(html.contains("var build = \"7\";"));
+        assert!(html.ends_with("</script>\n</body></html>"));
+    }
+
+    #[test]
+    fn decodes_escaped_url_segments() {
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%2e%2E"), "..");
+    }
+}
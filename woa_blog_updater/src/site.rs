@@ -0,0 +1,495 @@
+//! Renders `BlogPostsForJson` into a static site: an `index.html`, one
+//! `posts/<id>/index.html` per post and one `tags/<tag>/index.html` per tag,
+//! ready to publish by copying the output directory. Drafts and posts
+//! scheduled for later are left out. Each build lists the files it wrote in
+//! [`MANIFEST`], and the next one removes those it doesn't write again, so
+//! unpublished posts don't linger; anything else in the directory is left
+//! alone.
+//!
+//! Pages come from small HTML templates with `{{name}}` placeholders. The
+//! defaults are built in; any of them can be overridden by a file of the same
+//! name in the `--templates` directory.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::feed::{self, FeedSettings};
+use crate::{BlogError, BlogPostsForJson, BlogResult, ContentFormat, FooterLink, PostForJson, WoaTime};
+
+pub const POSTS_DIR: &str = "posts";
+const TAGS_DIR: &str = "tags";
+/// The files the last build wrote, one path per line relative to the output
+/// directory.
+pub const MANIFEST: &str = ".woa-build";
+
+struct Template {
+    name: &'static str,
+    default: &'static str,
+}
+
+const INDEX: Template = Template { name: "index.html", default: include_str!("templates/index.html") };
+const POST: Template = Template { name: "post.html", default: include_str!("templates/post.html") };
+const POST_SUMMARY: Template =
+    Template { name: "post_summary.html", default: include_str!("templates/post_summary.html") };
+const FOOTER_LINK: Template =
+    Template { name: "footer_link.html", default: include_str!("templates/footer_link.html") };
+const TAG: Template = Template { name: "tag.html", default: include_str!("templates/tag.html") };
+const TAG_LINK: Template = Template { name: "tag_link.html", default: include_str!("templates/tag_link.html") };
+
+struct Templates {
+    index: String,
+    post: String,
+    post_summary: String,
+    footer_link: String,
+    tag: String,
+    tag_link: String,
+}
+
+impl Templates {
+    fn load(dir: Option<&Path>) -> BlogResult<Templates> {
+        let read = |template: Template| -> BlogResult<String> {
+            match dir.map(|dir| dir.join(template.name)).filter(|path| path.exists()) {
+                Some(path) => fs::read_to_string(&path)
+                    .map_err(|error| BlogError::UnableToReadTemplate(format!("{}: {}", path.display(), error))),
+                None => Ok(template.default.to_string()),
+            }
+        };
+        Ok(Templates {
+            index: read(INDEX)?,
+            post: read(POST)?,
+            post_summary: read(POST_SUMMARY)?,
+            footer_link: read(FOOTER_LINK)?,
+            tag: read(TAG)?,
+            tag_link: read(TAG_LINK)?,
+        })
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Replaces each `{{name}}` with its value in one pass, so values that happen
+/// to contain braces are left alone. Unknown placeholders are kept as-is.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        filled.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                match values.iter().find(|(key, _)| *key == name) {
+                    Some((_, value)) => filled.push_str(value),
+                    None => filled.push_str(&rest[start..start + end + 4]),
+                }
+                rest = &after[end + 2..];
+            },
+            None => {
+                filled.push_str(&rest[start..]);
+                rest = "";
+            },
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.to_lowercase().chars() {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Whether `id` can name a post's directory without escaping `posts/`.
+pub fn is_usable_id(id: &str) -> bool {
+    !id.is_empty() && !id.contains(['/', '\\']) && id.trim() == id && id != "." && id != ".."
+}
+
+fn iso_date(woa_time: &WoaTime) -> String {
+    woa_time.to_iso().unwrap_or_default()
+}
+
+fn paragraphs(content: &[String], format: ContentFormat) -> String {
+    content
+        .iter()
+        .filter(|paragraph| !paragraph.trim().is_empty())
+        .map(|paragraph| match format {
+            ContentFormat::Text => format!("      <p>{}</p>\n", escape_html(paragraph.trim())),
+            ContentFormat::Html => format!("{}\n", paragraph.trim()),
+        })
+        .collect()
+}
+
+/// A post's body as HTML, whichever format it is stored in.
+pub fn render_content(post: &PostForJson) -> String {
+    paragraphs(&post.content, post.content_format)
+}
+
+/// How the page's `css` setting ends up in the `<head>`: a CSS file next to
+/// the posts file is copied into the site, anything with braces is inlined and
+/// anything else is linked to as a URL.
+enum Stylesheet {
+    None,
+    Inline(String),
+    Copied(String),
+    Linked(String),
+}
+
+impl Stylesheet {
+    fn from_setting(css: &str, posts_dir: &Path) -> Stylesheet {
+        let css = css.trim();
+        let local = posts_dir.join(css);
+        if css.is_empty() {
+            Stylesheet::None
+        } else if css.contains('{') {
+            Stylesheet::Inline(css.to_string())
+        } else if !css.contains("://") && local.is_file() {
+            Stylesheet::Copied(local.file_name().unwrap().to_string_lossy().to_string())
+        } else {
+            Stylesheet::Linked(css.to_string())
+        }
+    }
+
+    fn head_html(&self, root: &str) -> String {
+        match self {
+            Stylesheet::None => String::new(),
+            Stylesheet::Inline(css) => format!("<style>\n{}\n</style>", css),
+            Stylesheet::Copied(name) => {
+                format!("<link rel=\"stylesheet\" href=\"{}{}\">", root, escape_html(name))
+            },
+            Stylesheet::Linked(url) => format!("<link rel=\"stylesheet\" href=\"{}\">", escape_html(url)),
+        }
+    }
+}
+
+struct SiteWriter<'a> {
+    blog: &'a BlogPostsForJson,
+    templates: Templates,
+    stylesheet: Stylesheet,
+    feeds: Option<&'a FeedSettings>,
+    /// Each tag's directory under `tags/`.
+    tag_slugs: BTreeMap<&'a str, String>,
+    out_dir: PathBuf,
+    /// Everything this build has written, relative to `out_dir`.
+    written: RefCell<BTreeSet<PathBuf>>,
+}
+
+impl SiteWriter<'_> {
+    fn write(&self, relative: &Path, contents: &str) -> BlogResult<()> {
+        self.written.borrow_mut().insert(relative.to_path_buf());
+        let path = self.out_dir.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|error| BlogError::CouldNotWriteSite(format!("{}: {}", parent.display(), error)))?;
+        }
+        fs::write(&path, contents)
+            .map_err(|error| BlogError::CouldNotWriteSite(format!("{}: {}", path.display(), error)))
+    }
+
+    fn feed_links(&self, root: &str) -> String {
+        if self.feeds.is_none() {
+            return String::new();
+        }
+        format!(
+            "<link rel=\"alternate\" type=\"application/rss+xml\" title=\"RSS\" href=\"{root}{}\">\n  \
+             <link rel=\"alternate\" type=\"application/atom+xml\" title=\"Atom\" href=\"{root}{}\">",
+            feed::RSS_FILE,
+            feed::ATOM_FILE,
+        )
+    }
+
+    fn footer_links(&self, links: &[FooterLink]) -> String {
+        links
+            .iter()
+            .map(|link| {
+                fill(&self.templates.footer_link, &[
+                    ("label", &escape_html(&link.label)),
+                    ("url", &escape_html(&link.url)),
+                ])
+            })
+            .collect()
+    }
+
+    fn tag_links(&self, tags: &[String], root: &str) -> String {
+        tags.iter()
+            .map(|tag| {
+                fill(&self.templates.tag_link, &[
+                    ("tag", &escape_html(tag)),
+                    ("url", &format!("{}{}/{}/", root, TAGS_DIR, self.tag_slugs[tag.as_str()])),
+                ])
+                .trim()
+                .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn render_post(&self, post: &PostForJson) -> String {
+        let root = "../../";
+        fill(&self.templates.post, &[
+            ("page_title", &escape_html(&self.blog.page.title)),
+            ("css", &self.stylesheet.head_html(root)),
+            ("feeds", &self.feed_links(root)),
+            ("root", root),
+            ("title", &escape_html(&post.title)),
+            ("woa_time", &escape_html(&post.woa_time.to_string())),
+            ("date", &iso_date(&post.woa_time)),
+            ("tags", &self.tag_links(&post.tags, root)),
+            ("content", &paragraphs(&post.content, post.content_format)),
+            ("footer_links", &self.footer_links(&self.blog.footer_links)),
+        ])
+    }
+
+    fn summaries(&self, posts: &[&PostForJson], root: &str) -> String {
+        posts
+            .iter()
+            .map(|post| {
+                fill(&self.templates.post_summary, &[
+                    ("permalink", &format!("{}{}/{}/", root, POSTS_DIR, post.id)),
+                    ("title", &escape_html(&post.title)),
+                    ("tags", &self.tag_links(&post.tags, root)),
+                    ("woa_time", &escape_html(&post.woa_time.to_string())),
+                    ("date", &iso_date(&post.woa_time)),
+                    ("excerpt", &paragraphs(&post.content[..post.content.len().min(1)], post.content_format)),
+                    ("content", &paragraphs(&post.content, post.content_format)),
+                ])
+            })
+            .collect()
+    }
+
+    fn render_index(&self, posts: &[&PostForJson]) -> String {
+        fill(&self.templates.index, &[
+            ("page_title", &escape_html(&self.blog.page.title)),
+            ("css", &self.stylesheet.head_html("")),
+            ("feeds", &self.feed_links("")),
+            ("root", ""),
+            ("posts", &self.summaries(posts, "")),
+            ("footer_links", &self.footer_links(&self.blog.footer_links)),
+        ])
+    }
+
+    fn render_tag(&self, tag: &str, posts: &[&PostForJson]) -> String {
+        let root = "../../";
+        fill(&self.templates.tag, &[
+            ("page_title", &escape_html(&self.blog.page.title)),
+            ("tag", &escape_html(tag)),
+            ("css", &self.stylesheet.head_html(root)),
+            ("feeds", &self.feed_links(root)),
+            ("root", root),
+            ("posts", &self.summaries(posts, root)),
+            ("footer_links", &self.footer_links(&self.blog.footer_links)),
+        ])
+    }
+}
+
+/// The directory name for `tag`. A tag that only loses its case and spaces
+/// to [`slugify`] uses the slug itself; any other tag, such as "C++" or one
+/// with no letters or digits, adds a short hash of its name, so its page
+/// stays put whichever other tags exist.
+pub fn tag_slug(tag: &str) -> String {
+    let slug = slugify(tag);
+    let plain = tag.to_lowercase().split_whitespace().collect::<Vec<_>>().join("-");
+    if !slug.is_empty() && slug == plain {
+        return slug;
+    }
+    let base = if slug.is_empty() { "tag" } else { slug.as_str() };
+    format!("{}-{}", base, short_hash(tag))
+}
+
+/// The first six hex digits of the FNV-1a hash of `text`.
+fn short_hash(text: &str) -> String {
+    let hash = text
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3));
+    format!("{:016x}", hash)[..6].to_string()
+}
+
+/// A directory name for every tag, from [`tag_slug`]. Tags that differ only
+/// in case ("Rust" and "rust") would share one, so all but the lowercase
+/// spelling take the hashed form.
+fn tag_slugs<'a>(tags: impl IntoIterator<Item = &'a str>) -> BTreeMap<&'a str, String> {
+    let mut slugs: BTreeMap<&str, String> = tags.into_iter().map(|tag| (tag, tag_slug(tag))).collect();
+    let mut seen = HashSet::new();
+    let clashing: HashSet<String> = slugs.values().filter(|slug| !seen.insert(slug.as_str())).cloned().collect();
+    for (tag, slug) in slugs.iter_mut() {
+        if clashing.contains(slug) && *tag != slug.as_str() {
+            *slug = format!("{}-{}", slug, short_hash(tag));
+        }
+    }
+    slugs
+}
+
+/// Removes the files the previous build listed in its manifest that this
+/// one didn't write, along with directories that leaves empty, then records
+/// what this build wrote. Files no build wrote are never touched.
+fn remove_stale(out_dir: &Path, written: &BTreeSet<PathBuf>) -> BlogResult<()> {
+    let site_error =
+        |path: &Path, error: io::Error| BlogError::CouldNotWriteSite(format!("{}: {}", path.display(), error));
+    let manifest = out_dir.join(MANIFEST);
+    let previous = fs::read_to_string(&manifest).unwrap_or_default();
+    let stale = previous.lines().map(PathBuf::from).filter(|relative| !written.contains(relative));
+    for relative in stale {
+        // A manifest edited by hand mustn't reach outside the site.
+        if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+            continue;
+        }
+        let path = out_dir.join(&relative);
+        match fs::remove_file(&path) {
+            Ok(()) => {},
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {},
+            Err(error) => return Err(site_error(&path, error)),
+        }
+        for dir in relative.ancestors().skip(1).filter(|dir| !dir.as_os_str().is_empty()) {
+            if fs::remove_dir(out_dir.join(dir)).is_err() {
+                break;
+            }
+        }
+    }
+    let listing: String = written.iter().map(|relative| format!("{}\n", relative.display())).collect();
+    fs::write(&manifest, listing).map_err(|error| site_error(&manifest, error))
+}
+
+/// Writes the whole site for `blog` into `out_dir`, with feeds when `feeds` is
+/// given, and returns how many post pages were written.
+pub fn build(
+    blog: &BlogPostsForJson,
+    posts_file: &Path,
+    out_dir: &Path,
+    templates_dir: Option<&Path>,
+    feeds: Option<&FeedSettings>,
+) -> BlogResult<usize> {
+    let posts_dir = posts_file.parent().unwrap_or_else(|| Path::new("."));
+    let now = WoaTime::now();
+    let posts: Vec<&PostForJson> = blog.posts.iter().filter(|post| post.is_published(&now)).collect();
+    if let Some(post) = posts.iter().find(|post| !is_usable_id(&post.id)) {
+        return Err(BlogError::CouldNotWriteSite(format!(
+            "post '{}' has the id '{}', which can't be used as a directory name (see `validate`)",
+            post.title, post.id
+        )));
+    }
+    let mut tags: BTreeMap<&str, Vec<&PostForJson>> = BTreeMap::new();
+    for post in &posts {
+        for tag in &post.tags {
+            tags.entry(tag.as_str()).or_default().push(post);
+        }
+    }
+    let writer = SiteWriter {
+        blog,
+        templates: Templates::load(templates_dir)?,
+        stylesheet: Stylesheet::from_setting(&blog.page.css, posts_dir),
+        feeds,
+        tag_slugs: tag_slugs(tags.keys().copied()),
+        out_dir: out_dir.to_path_buf(),
+        written: RefCell::new(BTreeSet::new()),
+    };
+
+    for post in &posts {
+        writer.write(&Path::new(POSTS_DIR).join(&post.id).join("index.html"), &writer.render_post(post))?;
+    }
+    writer.write(Path::new("index.html"), &writer.render_index(&posts))?;
+
+    for (tag, tagged) in &tags {
+        let page = Path::new(TAGS_DIR).join(&writer.tag_slugs[tag]).join("index.html");
+        writer.write(&page, &writer.render_tag(tag, tagged))?;
+    }
+
+    let mut written = writer.written.take();
+    if let Stylesheet::Copied(name) = &writer.stylesheet {
+        fs::copy(posts_dir.join(name), out_dir.join(name))
+            .map_err(|error| BlogError::CouldNotWriteSite(format!("{}: {}", name, error)))?;
+        written.insert(PathBuf::from(name));
+    }
+    if let Some(settings) = feeds {
+        feed::write_feeds(blog, settings, out_dir)?;
+        written.extend([PathBuf::from(feed::RSS_FILE), PathBuf::from(feed::ATOM_FILE)]);
+    }
+    remove_stale(out_dir, &written)?;
+    Ok(posts.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_ids_that_leave_the_posts_directory() {
+        for id in ["", "../up", "a/b", "..", " a"] {
+            assert!(!is_usable_id(id), "{:?}", id);
+        }
+        assert!(is_usable_id("2024-05-01-hello"));
+    }
+
+    #[test]
+    fn removes_only_pages_an_earlier_build_wrote() {
+        let out = std::env::temp_dir().join(format!("woa-site-stale-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&out);
+        fs::create_dir_all(out.join(POSTS_DIR)).unwrap();
+        fs::write(out.join(POSTS_DIR).join("notes.md"), "kept by hand").unwrap();
+
+        let mut blog = BlogPostsForJson::default();
+        blog.posts.push(PostForJson {
+            id: "hello".to_string(),
+            woa_time: "2024:05:01:09:30:00".parse().unwrap(),
+            title: "Hello".to_string(),
+            content: vec!["Hi.".to_string()],
+            content_format: ContentFormat::Text,
+            tags: vec!["rust".to_string()],
+            draft: false,
+        });
+        let posts_file = out.join("posts.json");
+        build(&blog, &posts_file, &out, None, None).unwrap();
+        assert!(out.join("posts/hello/index.html").is_file());
+        assert!(out.join("tags/rust/index.html").is_file());
+
+        blog.posts[0].draft = true;
+        build(&blog, &posts_file, &out, None, None).unwrap();
+        assert!(!out.join("posts/hello").exists());
+        assert!(!out.join(TAGS_DIR).exists());
+        assert_eq!(fs::read_to_string(out.join(POSTS_DIR).join("notes.md")).unwrap(), "kept by hand");
+        fs::remove_dir_all(&out).unwrap();
+    }
+
+    #[test]
+    fn tag_pages_never_share_a_directory() {
+        let slugs = tag_slugs(["C", "C++", "c", "??", "Hello World"]);
+        assert_eq!(slugs["c"], "c");
+        assert_eq!(slugs["C"], format!("c-{}", short_hash("C")));
+        assert_eq!(slugs["C++"], format!("c-{}", short_hash("C++")));
+        assert_eq!(slugs["??"], format!("tag-{}", short_hash("??")));
+        assert_eq!(slugs["Hello World"], "hello-world");
+        assert_eq!(slugs.values().collect::<HashSet<_>>().len(), slugs.len());
+    }
+
+    #[test]
+    fn adding_a_tag_keeps_the_other_tags_pages() {
+        let before = tag_slugs(["C", "C++", "Rust"]);
+        let after = tag_slugs(["C", "C#", "C++", "Rust"]);
+        for tag in ["C", "C++", "Rust"] {
+            assert_eq!(before[tag], after[tag], "{}", tag);
+        }
+        assert_eq!(after["C"], "c");
+        assert_ne!(after["C#"], after["C++"]);
+    }
+}
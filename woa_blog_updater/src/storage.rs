@@ -0,0 +1,142 @@
+//! Reading and writing the posts file without ever losing it.
+//!
+//! A file that doesn't parse is reported with the line and column of the
+//! problem and left alone: nothing is saved over it. Every save first copies
+//! the current file into `<file>.backups/`, keeping the newest
+//! [`KEEP_BACKUPS`], then writes the new contents atomically.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use crate::{BlogError, BlogPostsForJson, BlogResult};
+
+pub const KEEP_BACKUPS: usize = 20;
+const BACKUP_STAMP: &str = "%Y%m%d-%H%M%S-%3f";
+/// How much of the offending line to show either side of a parse error.
+const SNIPPET_WIDTH: usize = 40;
+
+pub fn backup_dir(path: &Path) -> PathBuf {
+    let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!("{}.backups", file_name))
+}
+
+/// The file's contents, or `None` if it doesn't exist yet.
+pub fn read(path: &Path) -> BlogResult<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(BlogError::UnableToReadPostsFile(format!("{}: {}", path.display(), error))),
+    }
+}
+
+/// Describes where `text` failed to parse, with the line it failed on and a
+/// caret under the column.
+pub fn parse_error(path: &Path, text: &str, error: &serde_json::Error) -> BlogError {
+    let description = error.to_string();
+    let suffix = format!(" at line {} column {}", error.line(), error.column());
+    let description = description.strip_suffix(&suffix).unwrap_or(&description);
+    let mut message = format!("{}:{}:{}: {}", path.display(), error.line(), error.column(), description);
+    if let Some(line) = text.lines().nth(error.line().saturating_sub(1)) {
+        let chars: Vec<char> = line.chars().collect();
+        let column = error.column().saturating_sub(1).min(chars.len());
+        let start = column.saturating_sub(SNIPPET_WIDTH);
+        let end = (column + SNIPPET_WIDTH).min(chars.len());
+        let snippet: String = chars[start..end].iter().collect();
+        message.push_str(&format!("\n    {}\n    {}^", snippet, " ".repeat(column - start)));
+    }
+    if backup_dir(path).is_dir() {
+        message.push_str(&format!("\nThe copies saved before each change are in {}", backup_dir(path).display()));
+    }
+    BlogError::PostsFileUnParsable(message)
+}
+
+pub fn parse(path: &Path, text: &str) -> BlogResult<BlogPostsForJson> {
+    serde_json::from_str(text).map_err(|error| parse_error(path, text, &error))
+}
+
+/// Writes to a temporary file beside `path` and renames it into place, so a
+/// crash leaves either the old contents or the new ones, never half of each.
+fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name));
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+/// Copies `contents` into the backup directory and prunes all but the newest
+/// [`KEEP_BACKUPS`]. The timestamped names sort oldest first.
+fn backup(path: &Path, contents: &str) -> io::Result<()> {
+    let dir = backup_dir(path);
+    fs::create_dir_all(&dir)?;
+    let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let backup_path = dir.join(format!("{}.{}", file_name, Local::now().format(BACKUP_STAMP)));
+    write_atomically(&backup_path, contents)?;
+
+    let prefix = format!("{}.", file_name);
+    let mut backups: Vec<PathBuf> = fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.file_name().is_some_and(|name| name.to_string_lossy().starts_with(&prefix)))
+        .collect();
+    backups.sort();
+    for old in backups.iter().take(backups.len().saturating_sub(KEEP_BACKUPS)) {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// Saves `contents` over the posts file, backing up what was there first.
+/// Refuses if the file on disk no longer parses, since it may hold posts that
+/// were never loaded.
+pub fn save(path: &Path, contents: &str) -> BlogResult<()> {
+    let write_error = |error: io::Error| BlogError::CouldNotWritePostsFile(format!("{}: {}", path.display(), error));
+    if let Some(existing) = read(path)? {
+        if existing == contents {
+            return Ok(());
+        }
+        if let Err(error) = parse(path, &existing) {
+            return Err(BlogError::CouldNotWritePostsFile(format!(
+                "{}: refusing to overwrite a file that doesn't parse\n{}",
+                path.display(),
+                error
+            )));
+        }
+        backup(path, &existing).map_err(write_error)?;
+    }
+    write_atomically(path, contents).map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_errors_point_at_the_problem() {
+        let text = "{\n  \"page\": {\"title\": \"x\", \"css\": \"\"},\n  \"footer_links\": [],\n  \"posts\": [,]\n}";
+        let error = parse(Path::new("posts.json"), text).unwrap_err().to_string();
+        assert!(error.contains("posts.json:4:13: expected value\n"), "{}", error);
+        assert!(error.contains("\"posts\": [,]\n                ^"), "{}", error);
+    }
+
+    #[test]
+    fn saves_back_up_and_refuse_unparseable_files() {
+        let dir = std::env::temp_dir().join(format!("woa-storage-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("posts.json");
+        let blog = "{\"page\": {\"title\": \"\", \"css\": \"\"}, \"footer_links\": [], \"posts\": []}";
+
+        save(&path, blog).unwrap();
+        assert!(!backup_dir(&path).exists());
+        save(&path, &blog.replace("\"title\": \"\"", "\"title\": \"t\"")).unwrap();
+        assert_eq!(fs::read_dir(backup_dir(&path)).unwrap().count(), 1);
+
+        fs::write(&path, "{ not json").unwrap();
+        assert!(save(&path, blog).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{ not json");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
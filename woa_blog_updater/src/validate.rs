@@ -0,0 +1,210 @@
+//! The `validate` command: checks the posts file against what this tool
+//! expects without changing anything, so problems can be fixed by hand
+//! before a build or a save trips over them.
+//!
+//! The file is read more leniently than the other commands read it: times
+//! are kept as text, so a bad `woa_time` is reported along with everything
+//! else instead of stopping the file from loading at all.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::site::{is_usable_id, slugify, tag_slug};
+use crate::{storage, BlogResult, ContentFormat, FooterLink, Page, WoaTime};
+
+const TOP_KEYS: &[&str] = &["page", "footer_links", "posts", "unpublished"];
+const PAGE_KEYS: &[&str] = &["title", "css", "site_url", "author", "utc_offset"];
+const FOOTER_LINK_KEYS: &[&str] = &["label", "url"];
+const POST_KEYS: &[&str] = &["id", "woa_time", "title", "content", "content_format", "tags", "draft"];
+
+/// `BlogPostsForJson` with every post's `woa_time` left unparsed.
+#[derive(Deserialize)]
+pub struct UncheckedBlog {
+    page: Page,
+    footer_links: Vec<FooterLink>,
+    posts: Vec<UncheckedPost>,
+    #[serde(default)]
+    unpublished: Vec<UncheckedPost>,
+}
+
+impl UncheckedBlog {
+    pub fn post_count(&self) -> usize {
+        self.posts.len() + self.unpublished.len()
+    }
+}
+
+#[derive(Deserialize)]
+struct UncheckedPost {
+    #[serde(default)]
+    id: String,
+    woa_time: String,
+    title: String,
+    #[serde(rename = "content")]
+    _content: IgnoredAny,
+    #[serde(default, rename = "content_format")]
+    _content_format: ContentFormat,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    draft: bool,
+}
+
+/// Reads `text` for checking, along with the same file as plain JSON.
+pub fn parse(path: &Path, text: &str) -> BlogResult<(UncheckedBlog, Value)> {
+    let blog = serde_json::from_str(text).map_err(|error| storage::parse_error(path, text, &error))?;
+    let raw = serde_json::from_str(text).map_err(|error| storage::parse_error(path, text, &error))?;
+    Ok((blog, raw))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug)]
+pub struct Problem {
+    pub severity: Severity,
+    pub location: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", severity, self.location, self.message)
+    }
+}
+
+struct Checker {
+    problems: Vec<Problem>,
+}
+
+impl Checker {
+    fn report(&mut self, severity: Severity, location: &str, message: String) {
+        self.problems.push(Problem { severity, location: location.to_string(), message });
+    }
+
+    /// Fields serde would silently drop, and so lose on the next save.
+    fn unknown_keys(&mut self, value: Option<&Value>, known: &[&str], location: &str) {
+        let Some(Value::Object(object)) = value else {
+            return;
+        };
+        for key in object.keys().filter(|key| !known.contains(&key.as_str())) {
+            self.report(Severity::Warning, location, format!("unknown field '{}' will be dropped on the next save", key));
+        }
+    }
+}
+
+/// Everything wrong with an already-parsed posts file; `raw` is the same file
+/// as plain JSON, for spotting fields the structs don't know about.
+pub fn check(blog: &UncheckedBlog, raw: &Value) -> Vec<Problem> {
+    let mut checker = Checker { problems: Vec::new() };
+    checker.unknown_keys(Some(raw), TOP_KEYS, "file");
+    checker.unknown_keys(raw.get("page"), PAGE_KEYS, "page");
+    if blog.page.title.trim().is_empty() {
+        checker.report(Severity::Warning, "page", "the title is empty".to_string());
+    }
+
+    for (index, link) in blog.footer_links.iter().enumerate() {
+        let location = format!("footer link {}", index + 1);
+        checker.unknown_keys(raw.get("footer_links").and_then(|links| links.get(index)), FOOTER_LINK_KEYS, &location);
+        if link.url.trim().is_empty() {
+            checker.report(Severity::Warning, &location, format!("'{}' has no url", link.label));
+        }
+    }
+
+    let now = WoaTime::now();
+    let mut ids: HashMap<&str, String> = HashMap::new();
+    let arrays = [("posts", "post", &blog.posts), ("unpublished", "unpublished post", &blog.unpublished)];
+    for (key, label, posts) in arrays {
+        for (index, post) in posts.iter().enumerate() {
+            let location = match post.id.as_str() {
+                "" => format!("{} {}", label, index + 1),
+                id => format!("{} {} '{}'", label, index + 1, id),
+            };
+            checker.unknown_keys(raw.get(key).and_then(|posts| posts.get(index)), POST_KEYS, &location);
+
+            if post.id.is_empty() {
+                checker.report(Severity::Warning, &location, "no id; one will be assigned on the next save".to_string());
+            } else if !is_usable_id(&post.id) {
+                checker.report(Severity::Error, &location, "the id can't be used as a directory name".to_string());
+            } else if let Some(first) = ids.get(post.id.as_str()) {
+                checker.report(
+                    Severity::Warning,
+                    &location,
+                    format!("the id is also used by {}; this one will be renamed on the next save", first),
+                );
+            } else {
+                ids.insert(&post.id, format!("{} {}", label, index + 1));
+            }
+
+            match post.woa_time.parse::<WoaTime>() {
+                Err(_) => checker.report(
+                    Severity::Error,
+                    &location,
+                    format!("woa_time '{}' isn't YYYY:MM:DD:HH:MM:SS", post.woa_time),
+                ),
+                Ok(time) if key == "posts" && (post.draft || time > now) => checker.report(
+                    Severity::Warning,
+                    &location,
+                    "not published yet but visible to the frontend; it moves to unpublished on the next save"
+                        .to_string(),
+                ),
+                Ok(_) => {},
+            }
+            if post.title.trim().is_empty() {
+                checker.report(Severity::Warning, &location, "the title is empty".to_string());
+            }
+            for tag in &post.tags {
+                if slugify(tag).is_empty() {
+                    let message =
+                        format!("tag '{}' has no letters or digits, so its page is named '{}'", tag, tag_slug(tag));
+                    checker.report(Severity::Warning, &location, message);
+                }
+            }
+        }
+    }
+    checker.problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(json: &str) -> Vec<String> {
+        let (blog, raw) = parse(Path::new("posts.json"), json).unwrap();
+        check(&blog, &raw).iter().map(|problem| problem.to_string()).collect()
+    }
+
+    #[test]
+    fn a_clean_file_has_no_problems() {
+        let json = r#"{"page": {"title": "Blog", "css": ""}, "footer_links": [],
+            "posts": [{"id": "a", "woa_time": "2024:05:01:09:30:00", "title": "A", "content": []}]}"#;
+        assert!(problems(json).is_empty());
+    }
+
+    #[test]
+    fn reports_ids_times_and_unknown_fields() {
+        let json = r#"{"page": {"title": "Blog", "css": "", "theme": "dark"}, "footer_links": [],
+            "posts": [
+                {"id": "a", "woa_time": "2024-05-01", "title": "A", "content": []},
+                {"id": "a", "woa_time": "2024:05:01:09:30:00", "title": "B", "content": [], "drafted": true},
+                {"id": "../up", "woa_time": "2024:05:01:09:30:00", "title": "C", "content": []}
+            ]}"#;
+        assert_eq!(problems(json), vec![
+            "warning: page: unknown field 'theme' will be dropped on the next save",
+            "error: post 1 'a': woa_time '2024-05-01' isn't YYYY:MM:DD:HH:MM:SS",
+            "warning: post 2 'a': unknown field 'drafted' will be dropped on the next save",
+            "warning: post 2 'a': the id is also used by post 1; this one will be renamed on the next save",
+            "error: post 3 '../up': the id can't be used as a directory name",
+        ]);
+    }
+}
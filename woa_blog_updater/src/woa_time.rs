@@ -0,0 +1,181 @@
+//! The "soleilfou" time format, `YYYY:MM:DD:HH:MM:SS`, that the posts file
+//! and the blog frontend use for `woa_time`.
+//!
+//! A `WoaTime` is a wall-clock time with no zone of its own; it is read as
+//! local time wherever a real instant is needed (ISO 8601), except in feeds,
+//! which use the page's fixed `utc_offset` so they don't depend on the
+//! machine building them.
+//!
+//! Because the fields run from most to least significant, times order the
+//! same way as their strings.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::prelude::*;
+use clap::Subcommand;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{BlogError, BlogResult};
+
+const FORMAT: &str = "%Y:%m:%d:%H:%M:%S";
+/// ISO 8601 shapes without an offset, taken as wall-clock time.
+const NAIVE_ISO_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%d %H:%M"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct WoaTime(NaiveDateTime);
+
+impl WoaTime {
+    pub fn now() -> WoaTime {
+        WoaTime::from_datetime(&Local::now())
+    }
+
+    /// The wall-clock time of `time` in its own zone, to the second.
+    pub fn from_datetime<Tz: TimeZone>(time: &DateTime<Tz>) -> WoaTime {
+        let naive = time.naive_local();
+        WoaTime(naive.with_nanosecond(0).unwrap_or(naive))
+    }
+
+    pub fn date(self) -> NaiveDate {
+        self.0.date()
+    }
+
+    /// This wall-clock time in `zone`. Ambiguous times (when clocks go back)
+    /// take the earlier instant; times skipped when clocks go forward are `None`.
+    pub fn in_zone<Tz: TimeZone>(self, zone: &Tz) -> Option<DateTime<Tz>> {
+        zone.from_local_datetime(&self.0).earliest()
+    }
+
+    pub fn to_local(self) -> Option<DateTime<Local>> {
+        self.in_zone(&Local)
+    }
+
+    /// RFC 3339 (ISO 8601) with `zone`'s offset, e.g. `2024-05-01T09:30:00+02:00`.
+    pub fn to_iso_in<Tz: TimeZone>(self, zone: &Tz) -> BlogResult<String>
+    where
+        Tz::Offset: fmt::Display,
+    {
+        self.in_zone(zone)
+            .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true))
+            .ok_or_else(|| BlogError::NonexistentTime(self.to_string()))
+    }
+
+    pub fn to_iso(self) -> BlogResult<String> {
+        self.to_iso_in(&Local)
+    }
+
+    /// Parses ISO 8601. A time with an offset is converted into `zone`; one
+    /// without is taken as wall-clock time already, and a bare date as midnight.
+    pub fn from_iso_in<Tz: TimeZone>(text: &str, zone: &Tz) -> BlogResult<WoaTime> {
+        let text = text.trim();
+        if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+            return Ok(WoaTime::from_datetime(&time.with_timezone(zone)));
+        }
+        NAIVE_ISO_FORMATS
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+            .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().and_then(|day| day.and_hms_opt(0, 0, 0)))
+            .map(|naive| WoaTime(naive.with_nanosecond(0).unwrap_or(naive)))
+            .ok_or_else(|| BlogError::InvalidIsoTime(text.to_string()))
+    }
+
+    pub fn from_iso(text: &str) -> BlogResult<WoaTime> {
+        WoaTime::from_iso_in(text, &Local)
+    }
+}
+
+impl fmt::Display for WoaTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.format(FORMAT))
+    }
+}
+
+impl FromStr for WoaTime {
+    type Err = BlogError;
+
+    fn from_str(text: &str) -> BlogResult<WoaTime> {
+        NaiveDateTime::parse_from_str(text.trim(), FORMAT)
+            .map(WoaTime)
+            .map_err(|_| BlogError::InvalidWoaTime(text.to_string()))
+    }
+}
+
+impl Serialize for WoaTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for WoaTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<WoaTime, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum WoaTimeCommand {
+    /// Print the current time as a woa time
+    Now,
+    /// Convert a woa time to ISO 8601
+    ToIso {
+        time: String,
+        /// Give the time in UTC instead of with the local offset
+        #[arg(long)]
+        utc: bool,
+    },
+    /// Convert an ISO 8601 time to a woa time in local time
+    FromIso { time: String },
+}
+
+pub fn run(command: WoaTimeCommand) -> BlogResult<String> {
+    match command {
+        WoaTimeCommand::Now => Ok(WoaTime::now().to_string()),
+        WoaTimeCommand::ToIso { time, utc: false } => time.parse::<WoaTime>()?.to_iso(),
+        WoaTimeCommand::ToIso { time, utc: true } => {
+            let time: WoaTime = time.parse()?;
+            let instant = time.to_local().ok_or_else(|| BlogError::NonexistentTime(time.to_string()))?;
+            Ok(instant.with_timezone(&Utc).to_rfc3339_opts(SecondsFormat::Secs, true))
+        },
+        WoaTimeCommand::FromIso { time } => WoaTime::from_iso(&time).map(|time| time.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offset(hours: i32) -> FixedOffset {
+        FixedOffset::east_opt(hours * 3600).unwrap()
+    }
+
+    #[test]
+    fn parses_displays_and_orders() {
+        let time: WoaTime = "2024:05:01:09:30:00".parse().unwrap();
+        assert_eq!(time.to_string(), "2024:05:01:09:30:00");
+        assert_eq!(time.date(), NaiveDate::from_ymd_opt(2024, 5, 1).unwrap());
+        assert!(time < "2024:12:01:00:00:00".parse().unwrap());
+        assert!("2024-05-01T09:30:00".parse::<WoaTime>().is_err());
+        assert!("2024:13:01:09:30:00".parse::<WoaTime>().is_err());
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let time: WoaTime = serde_json::from_str("\"2024:05:01:09:30:00\"").unwrap();
+        assert_eq!(serde_json::to_string(&time).unwrap(), "\"2024:05:01:09:30:00\"");
+        let error = serde_json::from_str::<WoaTime>("\"2024-05-01\"").unwrap_err().to_string();
+        assert!(error.contains("'2024-05-01'"), "{}", error);
+    }
+
+    #[test]
+    fn converts_to_and_from_iso() {
+        let time: WoaTime = "2024:05:01:09:30:00".parse().unwrap();
+        assert_eq!(time.to_iso_in(&offset(2)).unwrap(), "2024-05-01T09:30:00+02:00");
+        assert_eq!(time.to_iso_in(&Utc).unwrap(), "2024-05-01T09:30:00Z");
+        assert_eq!(WoaTime::from_iso_in("2024-05-01T07:30:00Z", &offset(2)).unwrap(), time);
+        assert_eq!(WoaTime::from_iso_in("2024-05-01T09:30:00.250+02:00", &offset(2)).unwrap(), time);
+        assert_eq!(WoaTime::from_iso_in("2024-05-01T09:30", &offset(-5)).unwrap(), time);
+        assert_eq!(WoaTime::from_iso_in("2024-05-01", &Utc).unwrap().to_string(), "2024:05:01:00:00:00");
+        assert!(WoaTime::from_iso_in("yesterday", &Utc).is_err());
+    }
+}